@@ -0,0 +1,268 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+/// Converts output that contains ANSI escape sequences into styled lines. The current style is
+/// kept between lines since programs often set a color in one line and reset it in a later one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnsiParser {
+    pub style: Style,
+}
+
+impl AnsiParser {
+    /// Parse a line of output. SGR sequences (colors, bold, underline, ...) are converted to
+    /// styles, all other escape sequences are dropped. Text before a carriage return is
+    /// overwritten by the text after it, so progress lines collapse to their latest state.
+    pub fn parse_line(&mut self, text: &str) -> Line<'static> {
+        let mut spans: Vec<Span<'static>> = Vec::new();
+        let mut current = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => match chars.next() {
+                    // CSI: parameters followed by a final byte in the range @..~
+                    Some('[') => {
+                        let mut params = String::new();
+                        let mut final_byte = None;
+                        for c in chars.by_ref() {
+                            if ('@'..='~').contains(&c) {
+                                final_byte = Some(c);
+                                break;
+                            }
+                            params.push(c);
+                        }
+                        if final_byte == Some('m') {
+                            push_span(&mut spans, &mut current, self.style);
+                            self.apply_sgr(&params);
+                        }
+                    }
+                    // OSC (e.g. window title): terminated by BEL or ESC \
+                    Some(']') => {
+                        while let Some(c) = chars.next() {
+                            if c == '\x07' {
+                                break;
+                            }
+                            if c == '\x1b' && chars.peek() == Some(&'\\') {
+                                chars.next();
+                                break;
+                            }
+                        }
+                    }
+                    // any other escape sequence consists of a single character
+                    _ => {}
+                },
+                '\r' => {
+                    // a carriage return at the end of the line does not overwrite anything
+                    if chars.peek().is_some() {
+                        spans.clear();
+                        current.clear();
+                    }
+                }
+                '\t' => current.push_str("    "),
+                c if c.is_control() => {}
+                c => current.push(c),
+            }
+        }
+        push_span(&mut spans, &mut current, self.style);
+        Line::from(spans)
+    }
+
+    /// Apply the parameters of a "select graphic rendition" sequence to the current style
+    fn apply_sgr(&mut self, params: &str) {
+        let codes: Vec<u16> = params
+            .split([';', ':'])
+            .map(|code| code.parse().unwrap_or(0))
+            .collect();
+        // an empty parameter list is a reset
+        if codes.is_empty() {
+            self.style = Style::default();
+            return;
+        }
+
+        let mut codes = codes.into_iter();
+        while let Some(code) = codes.next() {
+            self.style = match code {
+                0 => Style::default(),
+                1 => self.style.add_modifier(Modifier::BOLD),
+                2 => self.style.add_modifier(Modifier::DIM),
+                3 => self.style.add_modifier(Modifier::ITALIC),
+                4 => self.style.add_modifier(Modifier::UNDERLINED),
+                5 | 6 => self.style.add_modifier(Modifier::SLOW_BLINK),
+                7 => self.style.add_modifier(Modifier::REVERSED),
+                8 => self.style.add_modifier(Modifier::HIDDEN),
+                9 => self.style.add_modifier(Modifier::CROSSED_OUT),
+                22 => self.style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+                23 => self.style.remove_modifier(Modifier::ITALIC),
+                24 => self.style.remove_modifier(Modifier::UNDERLINED),
+                25 => self.style.remove_modifier(Modifier::SLOW_BLINK),
+                27 => self.style.remove_modifier(Modifier::REVERSED),
+                28 => self.style.remove_modifier(Modifier::HIDDEN),
+                29 => self.style.remove_modifier(Modifier::CROSSED_OUT),
+                30..=37 => self.style.fg(basic_color(code - 30)),
+                38 => match extended_color(&mut codes) {
+                    Some(color) => self.style.fg(color),
+                    None => self.style,
+                },
+                39 => Style {
+                    fg: None,
+                    ..self.style
+                },
+                40..=47 => self.style.bg(basic_color(code - 40)),
+                48 => match extended_color(&mut codes) {
+                    Some(color) => self.style.bg(color),
+                    None => self.style,
+                },
+                49 => Style {
+                    bg: None,
+                    ..self.style
+                },
+                90..=97 => self.style.fg(basic_color(code - 90 + 8)),
+                100..=107 => self.style.bg(basic_color(code - 100 + 8)),
+                // unsupported attributes are ignored
+                _ => self.style,
+            };
+        }
+    }
+}
+
+/// Move the collected text into a new span with the given style
+fn push_span(spans: &mut Vec<Span<'static>>, current: &mut String, style: Style) {
+    if !current.is_empty() {
+        spans.push(Span::styled(std::mem::take(current), style));
+    }
+}
+
+/// Color of the 16 color palette
+fn basic_color(index: u16) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::Gray,
+        8 => Color::DarkGray,
+        9 => Color::LightRed,
+        10 => Color::LightGreen,
+        11 => Color::LightYellow,
+        12 => Color::LightBlue,
+        13 => Color::LightMagenta,
+        14 => Color::LightCyan,
+        _ => Color::White,
+    }
+}
+
+/// Parse the color of a 38/48 sequence: `5;n` for the 256 color palette, `2;r;g;b` for rgb. The
+/// components are consumed even if one of them is above 255, the color is ignored then.
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut component = || codes.next().map(u8::try_from);
+    match component()? {
+        Ok(5) => Some(Color::Indexed(component()?.ok()?)),
+        Ok(2) => {
+            let (r, g, b) = (component()?, component()?, component()?);
+            Some(Color::Rgb(r.ok()?, g.ok()?, b.ok()?))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text and style of every span of the line
+    fn spans(parser: &mut AnsiParser, text: &str) -> Vec<(String, Style)> {
+        parser
+            .parse_line(text)
+            .spans
+            .into_iter()
+            .map(|span| (span.content.into_owned(), span.style))
+            .collect()
+    }
+
+    #[test]
+    fn sgr_sets_and_resets_the_style() {
+        let mut parser = AnsiParser::default();
+        let bold_red = Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
+        assert_eq!(
+            spans(&mut parser, "a\x1b[1;31mb\x1b[22mc\x1b[0md"),
+            [
+                ("a".to_string(), Style::default()),
+                ("b".to_string(), bold_red),
+                (
+                    "c".to_string(),
+                    bold_red.remove_modifier(Modifier::BOLD | Modifier::DIM)
+                ),
+                ("d".to_string(), Style::default()),
+            ]
+        );
+        assert_eq!(
+            spans(&mut parser, "\x1b[92;104mx\x1b[39;49;mend"),
+            [
+                (
+                    "x".to_string(),
+                    Style::default().fg(Color::LightGreen).bg(Color::LightBlue)
+                ),
+                ("end".to_string(), Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn style_is_kept_between_lines() {
+        let mut parser = AnsiParser::default();
+        spans(&mut parser, "\x1b[4mstart");
+        assert_eq!(
+            spans(&mut parser, "next"),
+            [(
+                "next".to_string(),
+                Style::default().add_modifier(Modifier::UNDERLINED)
+            )]
+        );
+    }
+
+    #[test]
+    fn indexed_and_rgb_colors() {
+        let mut parser = AnsiParser::default();
+        spans(&mut parser, "\x1b[38;5;208m");
+        assert_eq!(parser.style.fg, Some(Color::Indexed(208)));
+        spans(&mut parser, "\x1b[48;2;10;20;30m");
+        assert_eq!(parser.style.bg, Some(Color::Rgb(10, 20, 30)));
+        // the colon form of the same sequence
+        spans(&mut parser, "\x1b[0;38:2:1:2:3m");
+        assert_eq!(parser.style.fg, Some(Color::Rgb(1, 2, 3)));
+    }
+
+    #[test]
+    fn colors_above_255_are_ignored() {
+        let mut parser = AnsiParser::default();
+        spans(&mut parser, "\x1b[38;5;300m");
+        assert_eq!(parser.style, Style::default());
+        // the components of the rejected color are not read as attributes (1 = bold, 4 = underline)
+        spans(&mut parser, "\x1b[38;2;256;1;4;3m");
+        assert_eq!(
+            parser.style,
+            Style::default().add_modifier(Modifier::ITALIC)
+        );
+    }
+
+    #[test]
+    fn carriage_return_keeps_the_latest_text() {
+        let mut parser = AnsiParser::default();
+        let line = parser.parse_line("10%\r50%\r\x1b[32m100%\r");
+        assert_eq!(line.to_string(), "100%");
+        assert_eq!(line.spans[0].style.fg, Some(Color::Green));
+    }
+
+    #[test]
+    fn other_escape_sequences_are_dropped() {
+        let mut parser = AnsiParser::default();
+        let line = parser.parse_line("\x1b]0;title\x07a\x1b[2Kb\x1b]8;;url\x1b\\c\td\x07");
+        assert_eq!(line.to_string(), "abc    d");
+        assert_eq!(parser.style, Style::default());
+    }
+}
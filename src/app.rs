@@ -1,56 +1,561 @@
-use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::{
+    bulk::{BulkOp, BulkRun},
+    clone::{clone_step, CloneList},
+    dashboard::Dashboard,
+    forge::ForgeConfig,
+    form::Form,
+    functions::{expand_home, RunOptions},
+    git::{find_repos, GitBackendKind},
+    menu::{Menu, MenuNode, VisibleNode},
+    output::DEFAULT_SCROLLBACK,
+    privilege::{root_access, PasswordCache},
+    review::{Review, ReviewFocus},
+    session::Sessions,
+    tasks::{ParamValues, Step, Task, Workflow},
+    validate::Problem,
+};
+
+// how long a toast is shown, errors are shown longer
+pub const TOAST_DURATION: Duration = Duration::from_secs(5);
+pub const ERROR_TOAST_DURATION: Duration = Duration::from_secs(10);
+
+/// Short message that is shown on top of the current screen, e.g. after a reload
+pub struct Toast {
+    pub message: String,
+    pub error: bool,
+    pub shown_at: Instant,
+    pub until_reload: bool, // a failed reload is shown until the next reload
+}
+
+impl Toast {
+    pub fn is_visible(&self) -> bool {
+        let duration = if self.error {
+            ERROR_TOAST_DURATION
+        } else {
+            TOAST_DURATION
+        };
+        self.until_reload || self.shown_at.elapsed() < duration
+    }
+}
 
 pub enum CurrentScreen {
-    Start,
-    Input,
+    Start, // the menu has the focus, the output of the selected session is shown next to it
+    Form,
+    Input, // the output and the input line of the selected session have the focus
+    Dashboard,
+    Clone,
+    Review,
+    Bulk,
+    Sessions, // list of the launched tasks and their state
 }
 
-#[derive(Debug, Deserialize)]
+/// Configuration of the app, read by `functions::read_config`
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
-    name: String,
-    git_repo_dir: String,
+    // user name at the git forge
+    #[serde(default = "default_name")]
+    pub name: String,
+    // directory that contains the git repositories
+    #[serde(default = "default_git_repo_dir")]
+    pub git_repo_dir: String,
+    // number of output lines that are kept for scrolling
+    #[serde(default = "default_scrollback")]
+    pub scrollback: usize,
+    // task catalog, searched in the config directories if not set
+    #[serde(default)]
+    pub task_file: Option<String>,
+    // git forge the remote repositories are listed from
+    #[serde(default)]
+    pub forge: ForgeConfig,
+    // problems that do not prevent the app from running, e.g. a git_repo_dir that does not exist
+    #[serde(skip)]
+    pub warnings: Vec<Problem>,
+    // implementation of the status, log and diff queries of the git screens
+    #[serde(default)]
+    pub git_backend: GitBackendKind,
+    // seconds the sudo password is kept in memory after it was entered, 0 asks every time
+    #[serde(default = "default_password_timeout")]
+    pub password_timeout: u64,
+}
+
+impl Config {
+    /// The warnings, one per line, None if there are none
+    pub fn warnings_text(&self) -> Option<String> {
+        let lines: Vec<String> = self.warnings.iter().map(Problem::to_string).collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// Config values that can be used as parameters (`{{git_repo_dir}}`) in the tasks
+    pub fn params(&self) -> ParamValues {
+        vec![
+            (
+                "git_repo_dir".to_string(),
+                Zeroizing::new(expand_home(&self.git_repo_dir)),
+            ),
+            ("name".to_string(), Zeroizing::new(self.name.clone())),
+        ]
+    }
+}
+
+fn default_name() -> String {
+    std::env::var("USER").unwrap_or_default()
+}
+
+fn default_git_repo_dir() -> String {
+    "~/git_repos".to_string()
+}
+
+fn default_scrollback() -> usize {
+    DEFAULT_SCROLLBACK
+}
+
+fn default_password_timeout() -> u64 {
+    300
 }
 
 pub struct App {
     pub current_screen: CurrentScreen, // the current screen the user is looking at, and will later determine what is rendered.
-    pub items: Vec<String>,
-    pub input_buffer: String,
-    pub show_password_prompt: bool,
-    pub activate_input_field: bool,
-    pub selected: usize,
-    pub selected_item: String,
+    pub tasks: Vec<Task>, // the tasks of the catalog that are shown in the start screen
+    pub menu: Menu,       // category tree of the tasks
+    pub visible_nodes: Vec<VisibleNode>, // menu nodes that are currently shown in the start screen
+    pub sessions: Sessions, // launched tasks with their output and input, shown as tabs
+    pub selected: usize,  // index into visible_nodes
+    pub form: Option<Form>, // parameters of the selected task
+    pub ready_task: Option<(String, Vec<Step>, RunOptions)>, // name and steps of the selected task with parameters, ready to run
+    pub config: Config,
+    pub toast: Option<Toast>,
+    pub dashboard: Dashboard,    // state of the repositories in git_repo_dir
+    pub clone_list: CloneList,   // repositories of the forge that can be cloned
+    pub review: Option<Review>,  // changes of the repository that is about to be pushed
+    pub bulk: Option<BulkRun>,   // last operation that was run for all repositories
+    pub password: PasswordCache, // sudo password of the tasks that require root
 }
 
 impl App {
-    pub fn new() -> App {
-        App {
+    pub fn new(tasks: Vec<Task>, config: Config) -> App {
+        let menu = Menu::from_tasks(&tasks);
+        let visible_nodes = menu.visible();
+        let mut app = App {
             current_screen: CurrentScreen::Start,
-            items: Vec::new(),
-            input_buffer: "".to_string(),
-            show_password_prompt: false,
-            activate_input_field: false,
+            tasks,
+            menu,
+            visible_nodes,
+            sessions: Sessions::default(),
             selected: 0,
-            selected_item: "".to_string(),
+            form: None,
+            ready_task: None,
+            config,
+            toast: None,
+            dashboard: Dashboard::default(),
+            clone_list: CloneList::default(),
+            review: None,
+            bulk: None,
+            password: PasswordCache::default(),
+        };
+        if let Some(warnings) = app.config.warnings_text() {
+            app.show_toast(warnings, false);
         }
+        app
     }
     pub fn next(&mut self) {
-        self.selected = (self.selected + 1) % self.items.len();
+        if self.visible_nodes.is_empty() {
+            return;
+        }
+        self.selected = (self.selected + 1) % self.visible_nodes.len();
     }
 
     pub fn previous(&mut self) {
+        if self.visible_nodes.is_empty() {
+            return;
+        }
         if self.selected == 0 {
-            self.selected = self.items.len() - 1;
+            self.selected = self.visible_nodes.len() - 1;
         } else {
             self.selected -= 1;
         }
     }
 
+    /// Path of the highlighted menu node
+    fn selected_path(&self) -> Option<Vec<usize>> {
+        self.visible_nodes
+            .get(self.selected)
+            .map(|node| node.path.clone())
+    }
+
+    /// Recalculate the shown menu nodes and highlight the node with the given path if it is
+    /// visible.
+    fn refresh_menu(&mut self, highlight: Option<&[usize]>) {
+        self.visible_nodes = self.menu.visible();
+        self.selected = highlight
+            .and_then(|path| self.visible_nodes.iter().position(|n| n.path == path))
+            .unwrap_or(0);
+    }
+
+    /// Expand (true) or collapse (false) the highlighted category
+    pub fn expand(&mut self, value: bool) {
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        self.menu.set_expanded(&path, value);
+        self.refresh_menu(Some(&path));
+    }
+
+    /// Open the highlighted category or select the highlighted task
     pub fn select(&mut self) {
-        self.selected_item = self.items[self.selected].clone();
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        match self.menu.node(&path) {
+            Some(MenuNode::Category { .. }) => {
+                self.menu.enter(&path);
+                self.refresh_menu(None);
+            }
+            Some(MenuNode::Task(i)) => {
+                let i = *i;
+                self.select_task(i);
+            }
+            None => {}
+        }
+    }
+
+    /// Select the task. If the task has parameters, the form to enter them is shown, otherwise
+    /// the task is started right away.
+    fn select_task(&mut self, index: usize) {
+        let Some(task) = self.tasks.get(index).cloned() else {
+            return;
+        };
+        if task.params.is_empty() {
+            self.start_task(task, Vec::new());
+        } else {
+            self.form = Some(Form::new(task));
+            self.current_screen = CurrentScreen::Form;
+        }
+    }
+
+    /// Validate the form and start the task with the entered parameters
+    pub fn submit_form(&mut self) {
+        let Some(form) = self.form.as_mut() else {
+            return;
+        };
+        if let Some(values) = form.submit() {
+            let task = form.task.clone();
+            self.form = None;
+            self.start_task(task, values);
+        }
+    }
+
+    /// Switch to the input screen and mark the task as ready to run. The values of the config are
+    /// available as parameters in addition to the entered ones.
+    fn start_task(&mut self, task: Task, mut values: ParamValues) {
+        values.extend(self.config.params());
+        let steps = task.steps_with_params(&values);
+        let mut options = task.run_options(&values);
+        if task.requires_root {
+            match root_access() {
+                Ok(tool) => options.root = tool,
+                Err(err) => {
+                    self.show_toast(format!("{} {}", task.name, err), true);
+                    return;
+                }
+            }
+            options.ask_password = true;
+        }
+        match (task.workflow, &options.working_dir) {
+            (Some(Workflow::Push), Some(repo)) => self.open_review(repo.clone()),
+            (Some(Workflow::Push), None) => {
+                self.show_toast(format!("{}: working_dir is not set", task.name), false)
+            }
+            (None, _) => self.run_steps(task.name, steps, options),
+        }
+    }
+
+    /// Mark the steps as ready to run and focus the output
+    fn run_steps(&mut self, name: String, steps: Vec<Step>, options: RunOptions) {
+        self.ready_task = Some((name, steps, options));
         self.current_screen = CurrentScreen::Input;
     }
 
+    /// Returns the name, steps and settings of the task that is ready to run
+    pub fn take_ready_task(&mut self) -> Option<(String, Vec<Step>, RunOptions)> {
+        self.ready_task.take()
+    }
+
+    /// Close the selected session, a running task has to be cancelled first
+    pub fn close_session(&mut self) {
+        if self.sessions.active().is_none() {
+            return;
+        }
+        if !self.sessions.close_selected() {
+            self.show_toast(
+                "The task is still running, cancel it with Ctrl+C first",
+                false,
+            );
+        } else if self.sessions.active().is_none() {
+            self.current_screen = CurrentScreen::Start;
+        }
+    }
+
+    /// Replace the config and the tasks with reloaded ones. The opened category, the expanded
+    /// categories and the highlighted node are kept if they still exist.
+    pub fn reload(&mut self, tasks: Vec<Task>, config: Config) {
+        let selected = self
+            .selected_path()
+            .map(|path| self.menu.names(&path, &self.tasks));
+        let current = self.menu.names(&self.menu.current, &self.tasks);
+        let expanded: Vec<Vec<String>> = self
+            .menu
+            .expanded()
+            .iter()
+            .map(|path| self.menu.names(path, &self.tasks))
+            .collect();
+
+        let mut menu = Menu::from_tasks(&tasks);
+        for names in expanded {
+            if let Some(path) = menu.find(&names, &tasks) {
+                menu.set_expanded(&path, true);
+            }
+        }
+        if let Some(path) = menu.find(&current, &tasks) {
+            menu.enter(&path);
+        }
+        let highlight = selected.and_then(|names| menu.find(&names, &tasks));
+
+        self.menu = menu;
+        self.tasks = tasks;
+        for session in &mut self.sessions.sessions {
+            session.run.output.set_capacity(config.scrollback);
+        }
+        self.config = config;
+        self.refresh_menu(highlight.as_deref());
+        // git_repo_dir may have changed
+        self.dashboard.refresh();
+    }
+
+    /// Show the state of the git repositories
+    pub fn open_dashboard(&mut self) {
+        let dir = PathBuf::from(expand_home(&self.config.git_repo_dir));
+        if !dir.exists() {
+            self.show_toast(
+                format!(
+                    "{} does not exist, set git_repo_dir or clone a repository",
+                    dir.display()
+                ),
+                false,
+            );
+        }
+        self.dashboard.refresh();
+        self.current_screen = CurrentScreen::Dashboard;
+    }
+
+    /// Show the repositories of the forge, they are fetched again every time the list is opened
+    pub fn open_clone_list(&mut self) {
+        self.clone_list.fetch = true;
+        self.current_screen = CurrentScreen::Clone;
+    }
+
+    /// Clone the selected repositories into git_repo_dir, one step per repository. The clones
+    /// run in a terminal so that git shows its progress.
+    pub fn clone_marked(&mut self) {
+        let steps: Vec<Step> = self
+            .clone_list
+            .marked_repos()
+            .into_iter()
+            .map(clone_step)
+            .collect();
+        if steps.is_empty() {
+            self.show_toast("Select the repositories to clone with space", false);
+            return;
+        }
+        let dir = PathBuf::from(expand_home(&self.config.git_repo_dir));
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            self.show_toast(format!("Can not create {}: {}", dir.display(), err), true);
+            return;
+        }
+        let options = RunOptions {
+            pty: true,
+            working_dir: Some(dir),
+            ..RunOptions::default()
+        };
+        self.dashboard.refresh();
+        self.run_steps("Clone repositories".to_string(), steps, options);
+    }
+
+    /// Run the operation for all repositories in git_repo_dir and show the results. Only one
+    /// operation runs at a time.
+    pub fn start_bulk(&mut self, op: BulkOp) {
+        if self.bulk.as_ref().is_some_and(BulkRun::is_running) {
+            self.show_toast("Wait until the running operation is finished", false);
+            self.current_screen = CurrentScreen::Bulk;
+            return;
+        }
+        let dir = PathBuf::from(expand_home(&self.config.git_repo_dir));
+        self.bulk = Some(BulkRun::new(op, find_repos(&dir)));
+        self.current_screen = CurrentScreen::Bulk;
+    }
+
+    /// Show the changes of the repository to select the files that are committed
+    pub fn open_review(&mut self, repo: PathBuf) {
+        self.review = Some(Review::new(repo, self.config.git_backend.backend()));
+        self.current_screen = CurrentScreen::Review;
+    }
+
+    /// Commit the staged files of the review with the entered message and push the commit
+    pub fn commit_and_push(&mut self) {
+        let Some(review) = self.review.as_mut() else {
+            return;
+        };
+        if review.is_busy() {
+            self.show_toast("Wait until the changes are read", false);
+            return;
+        }
+        if !review.has_staged_files() {
+            self.show_toast("Stage at least one file with space", false);
+            return;
+        }
+        if review.message.is_empty() {
+            review.focus = ReviewFocus::Message;
+            self.show_toast("Enter a commit message", false);
+            return;
+        }
+        let steps = review.commit_steps();
+        // git asks for credentials only on a terminal
+        let options = RunOptions {
+            pty: true,
+            working_dir: Some(review.repo.clone()),
+            ..RunOptions::default()
+        };
+        let name = format!("Push {}", review.repo.display());
+        self.review = None;
+        self.dashboard.refresh();
+        self.run_steps(name, steps, options);
+    }
+
+    pub fn show_toast(&mut self, message: impl Into<String>, error: bool) {
+        self.toast = Some(Toast {
+            message: message.into(),
+            error,
+            shown_at: Instant::now(),
+            until_reload: false,
+        });
+    }
+
+    /// Show the problems of a failed reload until the configuration is reloaded again
+    pub fn show_reload_error(&mut self, message: impl Into<String>) {
+        self.show_toast(message, true);
+        if let Some(toast) = self.toast.as_mut() {
+            toast.until_reload = true;
+        }
+    }
+
+    /// Return to the start screen. If the start screen is already shown, go up one level in the
+    /// category tree. The clone list and the bulk results return to the dashboard they were opened
+    /// from.
     pub fn back_to_start(&mut self) {
+        match self.current_screen {
+            CurrentScreen::Start => {
+                if let Some(left) = self.menu.leave() {
+                    self.refresh_menu(Some(&left));
+                }
+                return;
+            }
+            CurrentScreen::Clone | CurrentScreen::Bulk => {
+                self.current_screen = CurrentScreen::Dashboard;
+                return;
+            }
+            _ => {}
+        }
+        self.form = None;
+        self.review = None;
         self.current_screen = CurrentScreen::Start;
     }
+
+    /// Move the focus between the menu and the output of the run that is shown next to it
+    pub fn toggle_focus(&mut self) {
+        self.current_screen = match self.current_screen {
+            CurrentScreen::Start if self.sessions.active().is_some() => CurrentScreen::Input,
+            CurrentScreen::Input => CurrentScreen::Start,
+            _ => return,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::TaskCatalog;
+
+    const TASKS: &str = r#"
+[[task]]
+name = "status"
+category = "system/services"
+steps = []
+
+[[task]]
+name = "update"
+category = "system"
+steps = []
+"#;
+
+    fn catalog(toml: &str) -> Vec<Task> {
+        toml::from_str::<TaskCatalog>(toml).unwrap().tasks
+    }
+
+    fn config() -> Config {
+        toml::from_str("").unwrap()
+    }
+
+    /// Names of the highlighted node and its categories
+    fn highlighted(app: &App) -> Vec<String> {
+        app.menu.names(&app.selected_path().unwrap(), &app.tasks)
+    }
+
+    /// Names of the shown nodes
+    fn shown(app: &App) -> Vec<String> {
+        app.visible_nodes
+            .iter()
+            .map(|node| app.menu.names(&node.path, &app.tasks).pop().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reload_keeps_the_highlighted_node() {
+        let mut app = App::new(catalog(TASKS), config());
+        app.select();
+        app.expand(true);
+        app.next();
+        app.next();
+        assert_eq!(shown(&app), ["services", "status", "update"]);
+        assert_eq!(highlighted(&app), ["system", "update"]);
+
+        // a new category moves the nodes
+        let changed = format!(
+            "{}\n[[task]]\nname = \"backup\"\ncategory = \"system/backup\"\nsteps = []\n",
+            TASKS
+        );
+        let backup_first = catalog(&changed).into_iter().rev().collect();
+        app.reload(backup_first, config());
+        assert_eq!(app.menu.breadcrumb(), ["system"]);
+        assert_eq!(shown(&app), ["backup", "services", "status", "update"]);
+        assert_eq!(highlighted(&app), ["system", "update"]);
+    }
+
+    #[test]
+    fn reload_highlights_the_first_node_if_the_task_is_gone() {
+        let mut app = App::new(catalog(TASKS), config());
+        app.select();
+        app.next();
+        assert_eq!(highlighted(&app), ["system", "update"]);
+        let without_update = catalog(TASKS).into_iter().take(1).collect();
+        app.reload(without_update, config());
+        assert_eq!(shown(&app), ["services"]);
+        assert_eq!(app.selected, 0);
+    }
 }
@@ -0,0 +1,359 @@
+use std::{path::PathBuf, sync::Arc};
+
+use tokio::sync::{mpsc::Sender, Semaphore};
+
+use crate::git::{git, read_status, GitBackend};
+
+// number of repositories that are processed at the same time, fetch, pull and push use the network
+const MAX_PARALLEL_OPS: usize = 4;
+
+/// Operation that is run for all repositories in git_repo_dir
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkOp {
+    Fetch,
+    Pull, // fast-forward only, repositories with uncommitted changes are skipped
+    Push, // only repositories that are ahead of their upstream
+    Dirty,
+}
+
+impl BulkOp {
+    pub fn title(self) -> &'static str {
+        match self {
+            BulkOp::Fetch => "Fetch all",
+            BulkOp::Pull => "Pull all (fast-forward only)",
+            BulkOp::Push => "Push all that are ahead",
+            BulkOp::Dirty => "Repositories with uncommitted changes",
+        }
+    }
+}
+
+/// Result of the operation for one repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Failed,
+    Skipped,
+    Dirty, // the repository has uncommitted changes, only reported by BulkOp::Dirty
+}
+
+/// Row of the result table
+#[derive(Debug, Clone)]
+pub struct BulkResult {
+    pub name: String,
+    pub outcome: Option<Outcome>, // None while the repository is not processed yet
+    pub reason: String,
+}
+
+/// Operation that runs or ran for all repositories in git_repo_dir
+pub struct BulkRun {
+    pub op: BulkOp,
+    pub repos: Vec<PathBuf>,
+    pub results: Vec<BulkResult>,
+    pub selected: usize,
+    pub started: bool, // the operation was spawned
+}
+
+impl BulkRun {
+    pub fn new(op: BulkOp, repos: Vec<PathBuf>) -> BulkRun {
+        let results = repos
+            .iter()
+            .map(|path| BulkResult {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                outcome: None,
+                reason: String::new(),
+            })
+            .collect();
+        BulkRun {
+            op,
+            repos,
+            results,
+            selected: 0,
+            started: false,
+        }
+    }
+
+    pub fn next(&mut self) {
+        if !self.results.is_empty() {
+            self.selected = (self.selected + 1) % self.results.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.results.is_empty() {
+            self.selected = (self.selected + self.results.len() - 1) % self.results.len();
+        }
+    }
+
+    /// Show the result of a repository
+    pub fn update(&mut self, index: usize, outcome: Outcome, reason: String) {
+        if let Some(result) = self.results.get_mut(index) {
+            result.outcome = Some(outcome);
+            result.reason = reason;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started && self.results.iter().any(|result| result.outcome.is_none())
+    }
+
+    /// Number of repositories with the outcome
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.outcome == Some(outcome))
+            .count()
+    }
+}
+
+/// Run the operation for all repositories in the background. The result of each repository is
+/// sent with its index as soon as it is finished.
+pub fn spawn_bulk(
+    op: BulkOp,
+    repos: Vec<PathBuf>,
+    git: Arc<dyn GitBackend>,
+    tx: Sender<(usize, Outcome, String)>,
+) {
+    let semaphore = Arc::new(Semaphore::new(MAX_PARALLEL_OPS));
+    for (i, path) in repos.into_iter().enumerate() {
+        let semaphore = Arc::clone(&semaphore);
+        let git = Arc::clone(&git);
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let _permit = semaphore.acquire().await;
+            let (outcome, reason) = run_op(op, path, git).await;
+            let _ = tx.send((i, outcome, reason)).await;
+        });
+    }
+}
+
+/// Run the operation for one repository and return its outcome and the reason for it
+async fn run_op(op: BulkOp, path: PathBuf, git_backend: Arc<dyn GitBackend>) -> (Outcome, String) {
+    let status = read_status(git_backend, path).await;
+    if let Some(err) = &status.error {
+        return (Outcome::Failed, error_reason(err));
+    }
+    let repo = &status.path;
+    let result = match op {
+        BulkOp::Dirty => {
+            return if status.changed == 0 && status.untracked == 0 {
+                (Outcome::Ok, "clean".to_string())
+            } else {
+                (
+                    Outcome::Dirty,
+                    format!("{} changed, {} untracked", status.changed, status.untracked),
+                )
+            };
+        }
+        BulkOp::Fetch => {
+            match git(repo, &["remote"]).await {
+                Ok(remotes) if remotes.trim().is_empty() => {
+                    return (Outcome::Skipped, "no remote".to_string())
+                }
+                Ok(_) => {}
+                Err(err) => return (Outcome::Failed, error_reason(&err)),
+            }
+            git(repo, &["fetch", "--all", "--prune"])
+                .await
+                .map(|_| "fetched".to_string())
+        }
+        BulkOp::Pull | BulkOp::Push => {
+            if status.branch.is_none() {
+                return (Outcome::Skipped, "detached HEAD".to_string());
+            }
+            let Some((ahead, _)) = status.ahead_behind else {
+                return (Outcome::Skipped, "no upstream branch".to_string());
+            };
+            if op == BulkOp::Pull && status.changed > 0 {
+                // the fast-forward could stop halfway because of a local change
+                return (Outcome::Skipped, "uncommitted changes".to_string());
+            }
+            if op == BulkOp::Pull {
+                git(repo, &["pull", "--ff-only"]).await.map(|output| {
+                    if output.contains("Already up to date") {
+                        "already up to date".to_string()
+                    } else {
+                        "fast-forwarded".to_string()
+                    }
+                })
+            } else if ahead == 0 {
+                return (Outcome::Skipped, "nothing to push".to_string());
+            } else {
+                git(repo, &["push"])
+                    .await
+                    .map(|_| format!("pushed {} commit(s)", ahead))
+            }
+        }
+    };
+    match result {
+        Ok(reason) => (Outcome::Ok, reason),
+        Err(err) => (Outcome::Failed, error_reason(&err)),
+    }
+}
+
+/// The line of git's stderr that explains the failure, git prints hints around it
+fn error_reason(stderr: &str) -> String {
+    let mut lines = stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    lines
+        .clone()
+        .find(|line| line.starts_with("fatal:") || line.starts_with("error:"))
+        .or_else(|| lines.next())
+        .unwrap_or("unknown error")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        git::GitBackendKind,
+        test_repo::{init_repo, run_git, write, TempDir},
+    };
+
+    fn run(op: BulkOp) -> BulkRun {
+        let repos = ["/repos/a", "/repos/b", "/repos/c", "/repos/d"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        BulkRun::new(op, repos)
+    }
+
+    #[test]
+    fn results_are_counted_by_outcome() {
+        let mut run = run(BulkOp::Push);
+        let names: Vec<_> = run
+            .results
+            .iter()
+            .map(|result| result.name.as_str())
+            .collect();
+        assert_eq!(names, ["a", "b", "c", "d"]);
+        assert!(!run.is_running());
+        run.started = true;
+        run.update(2, Outcome::Failed, "rejected".to_string());
+        run.update(0, Outcome::Ok, "pushed 1 commit(s)".to_string());
+        run.update(9, Outcome::Ok, String::new());
+        assert!(run.is_running());
+        assert_eq!(run.count(Outcome::Ok), 1);
+        assert_eq!(run.count(Outcome::Failed), 1);
+        assert_eq!(run.results[2].reason, "rejected");
+        run.update(1, Outcome::Skipped, "nothing to push".to_string());
+        run.update(3, Outcome::Skipped, "no upstream branch".to_string());
+        assert!(!run.is_running());
+        assert_eq!(run.count(Outcome::Skipped), 2);
+        assert_eq!(run.count(Outcome::Dirty), 0);
+    }
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut run = run(BulkOp::Dirty);
+        run.previous();
+        assert_eq!(run.selected, 3);
+        run.next();
+        assert_eq!(run.selected, 0);
+        let mut empty = BulkRun::new(BulkOp::Fetch, Vec::new());
+        empty.next();
+        empty.previous();
+        assert_eq!(empty.selected, 0);
+    }
+
+    #[test]
+    fn error_reason_is_the_fatal_line() {
+        let stderr = "hint: Updates were rejected\n\nerror: failed to push some refs\nhint: more";
+        assert_eq!(error_reason(stderr), "error: failed to push some refs");
+        assert_eq!(
+            error_reason("\n  Connection timed out  \nmore"),
+            "Connection timed out"
+        );
+        assert_eq!(error_reason(" \n"), "unknown error");
+    }
+
+    /// Bare remote with one commit and the clones a and b of it
+    fn remote_and_clones(temp: &TempDir) -> (PathBuf, PathBuf) {
+        let work = temp.0.join("work");
+        init_repo(&work, &[("file.txt", "one\n")]);
+        run_git(&temp.0, &["clone", "-q", "--bare", "work", "remote.git"]);
+        run_git(&temp.0, &["clone", "-q", "remote.git", "a"]);
+        run_git(&temp.0, &["clone", "-q", "remote.git", "b"]);
+        (temp.0.join("a"), temp.0.join("b"))
+    }
+
+    fn commit(repo: &Path, file: &str) {
+        write(repo, file, "change\n");
+        run_git(repo, &["add", "."]);
+        run_git(repo, &["commit", "-q", "-m", file]);
+    }
+
+    async fn op(op: BulkOp, repo: &Path) -> (Outcome, String) {
+        run_op(op, repo.to_path_buf(), GitBackendKind::Native.backend()).await
+    }
+
+    #[tokio::test]
+    async fn push_and_pull_between_clones() {
+        let temp = TempDir::new("bulk-sync");
+        let (a, b) = remote_and_clones(&temp);
+        let nothing = (Outcome::Skipped, "nothing to push".to_string());
+        assert_eq!(op(BulkOp::Push, &a).await, nothing);
+
+        commit(&a, "a.txt");
+        let pushed = (Outcome::Ok, "pushed 1 commit(s)".to_string());
+        assert_eq!(op(BulkOp::Push, &a).await, pushed);
+
+        // b does not know about the commit of a yet, its push is rejected
+        commit(&b, "b.txt");
+        let (outcome, reason) = op(BulkOp::Push, &b).await;
+        assert_eq!(outcome, Outcome::Failed);
+        assert!(reason.starts_with("error: failed to push"), "{}", reason);
+
+        // a is fast-forwarded to the commit of b after b moved its commit on top
+        run_git(&b, &["pull", "-q", "--rebase"]);
+        assert_eq!(op(BulkOp::Push, &b).await, pushed);
+        let fetched = (Outcome::Ok, "fetched".to_string());
+        assert_eq!(op(BulkOp::Fetch, &a).await, fetched);
+        let pulled = (Outcome::Ok, "fast-forwarded".to_string());
+        assert_eq!(op(BulkOp::Pull, &a).await, pulled);
+        assert!(a.join("b.txt").exists());
+        let up_to_date = (Outcome::Ok, "already up to date".to_string());
+        assert_eq!(op(BulkOp::Pull, &a).await, up_to_date);
+    }
+
+    #[tokio::test]
+    async fn dirty_repositories_are_reported_and_not_pulled() {
+        let temp = TempDir::new("bulk-dirty");
+        let (a, b) = remote_and_clones(&temp);
+        let clean = (Outcome::Ok, "clean".to_string());
+        assert_eq!(op(BulkOp::Dirty, &a).await, clean);
+
+        commit(&b, "b.txt");
+        run_git(&b, &["push", "-q"]);
+        write(&a, "file.txt", "local\n");
+        write(&a, "new.txt", "new\n");
+        let dirty = (Outcome::Dirty, "1 changed, 1 untracked".to_string());
+        assert_eq!(op(BulkOp::Dirty, &a).await, dirty);
+        run_git(&a, &["fetch", "-q"]);
+        let skipped = (Outcome::Skipped, "uncommitted changes".to_string());
+        assert_eq!(op(BulkOp::Pull, &a).await, skipped);
+        assert!(!a.join("b.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn repositories_without_remote_or_upstream_are_skipped() {
+        let temp = TempDir::new("bulk-local");
+        let repo = temp.0.join("local");
+        init_repo(&repo, &[("file.txt", "one\n")]);
+        let no_remote = (Outcome::Skipped, "no remote".to_string());
+        assert_eq!(op(BulkOp::Fetch, &repo).await, no_remote);
+        let no_upstream = (Outcome::Skipped, "no upstream branch".to_string());
+        assert_eq!(op(BulkOp::Pull, &repo).await, no_upstream);
+        assert_eq!(op(BulkOp::Push, &repo).await, no_upstream);
+
+        let (outcome, _) = op(BulkOp::Fetch, &temp.0.join("missing")).await;
+        assert_eq!(outcome, Outcome::Failed);
+    }
+}
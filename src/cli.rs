@@ -0,0 +1,189 @@
+use anyhow::{bail, Context};
+use zeroize::Zeroizing;
+
+use crate::tasks::ParamValues;
+
+/// Command line arguments of linutil_rs
+#[derive(Debug, Default)]
+pub struct CliArgs {
+    // use only this config file instead of the system and user config
+    pub config_file: Option<String>,
+    // config keys that are set on the command line, e.g. `--git-repo-dir ~/src`
+    pub overrides: Vec<(String, String)>,
+    pub help: bool,
+    // only validate the configuration and exit
+    pub check_config: bool,
+    // run without the user interface
+    pub command: Option<Subcommand>,
+}
+
+/// Commands that are run without the user interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subcommand {
+    List,
+    Describe {
+        task: String,
+    },
+    Run {
+        task: String,
+        // values of `--param key=value`, they may be passwords and are wiped when they are
+        // dropped. The arguments of the process stay visible to other users in `ps` though.
+        params: ParamValues,
+        json: bool, // print the events of the run as JSON lines
+    },
+}
+
+pub const USAGE: &str = "Usage: linutil_rs [OPTIONS] [COMMAND]
+
+Commands:
+  list                list the tasks of the catalog
+  describe <TASK>     show the parameters and steps of a task
+  run <TASK>          run a task without the user interface and exit with its exit code
+
+Tasks are selected by name or by category/name. Without a command the user interface is started.
+
+Options:
+  --config <FILE>     read the configuration only from this file
+  --check-config      validate the configuration and the task catalog and exit
+  --param <KEY=VALUE> value of a parameter of the task for `run`, can be repeated
+  --json              print the events of `run` as JSON, one object per line
+  --<key> <value>     override a key of the configuration, e.g. --git-repo-dir ~/src
+  --<key>=<value>     same as above
+  -h, --help          show this help";
+
+/// Parse the command line arguments (without the program name). Every unknown `--key` sets the
+/// config key of the same name, dashes are replaced by underscores.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<CliArgs> {
+    let mut cli_args = CliArgs::default();
+    let mut positional = Vec::new();
+    let mut params = Vec::new();
+    let mut json = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // the argument may contain the value of a password parameter (`--param=pw=...`)
+        let arg = Zeroizing::new(arg);
+        if *arg == "-h" || *arg == "--help" {
+            cli_args.help = true;
+            continue;
+        }
+        if *arg == "--check-config" {
+            cli_args.check_config = true;
+            continue;
+        }
+        if *arg == "--json" {
+            json = true;
+            continue;
+        }
+        let Some(option) = arg.strip_prefix("--") else {
+            positional.push(arg.to_string());
+            continue;
+        };
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => {
+                let value = args
+                    .next()
+                    .with_context(|| format!("missing value for --{}", option))?;
+                (option.to_string(), value)
+            }
+        };
+        if key == "config" {
+            cli_args.config_file = Some(value);
+        } else if key == "param" {
+            let value = Zeroizing::new(value);
+            let Some((name, value)) = value.split_once('=') else {
+                bail!("--param expects key=value, got '{}'", *value);
+            };
+            params.push((name.to_string(), Zeroizing::new(value.to_string())));
+        } else {
+            cli_args.overrides.push((key.replace('-', "_"), value));
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    cli_args.command = match positional.next().as_deref() {
+        None => None,
+        Some("list") => Some(Subcommand::List),
+        Some(command @ ("describe" | "run")) => {
+            let task = positional
+                .next()
+                .with_context(|| format!("missing task for {}\n\n{}", command, USAGE))?;
+            if command == "run" {
+                Some(Subcommand::Run {
+                    task,
+                    params: std::mem::take(&mut params),
+                    json: std::mem::take(&mut json),
+                })
+            } else {
+                Some(Subcommand::Describe { task })
+            }
+        }
+        Some(arg) => bail!("unexpected argument '{}'\n\n{}", arg, USAGE),
+    };
+    if let Some(arg) = positional.next() {
+        bail!("unexpected argument '{}'\n\n{}", arg, USAGE);
+    }
+    if !params.is_empty() {
+        bail!("--param is only supported by run");
+    }
+    if json {
+        bail!("--json is only supported by run");
+    }
+    Ok(cli_args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<CliArgs> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn keys_are_overrides_with_underscores() {
+        let args = parse(&["--git-repo-dir", "~/src", "--scrollback=500", "--name=a=b"]).unwrap();
+        assert_eq!(
+            args.overrides,
+            [
+                ("git_repo_dir".to_string(), "~/src".to_string()),
+                ("scrollback".to_string(), "500".to_string()),
+                ("name".to_string(), "a=b".to_string()),
+            ]
+        );
+        assert_eq!(args.config_file, None);
+        assert!(!args.help);
+    }
+
+    #[test]
+    fn config_file_and_help() {
+        let args = parse(&["--config", "/tmp/config.toml", "-h"]).unwrap();
+        assert_eq!(args.config_file.as_deref(), Some("/tmp/config.toml"));
+        assert!(args.overrides.is_empty());
+        assert!(args.help);
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        let err = parse(&["--name"]).unwrap_err();
+        assert_eq!(err.to_string(), "missing value for --name");
+        let err = parse(&["positional"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("unexpected argument 'positional'"));
+    }
+
+    #[test]
+    fn params_are_split_at_the_first_equals_sign() {
+        let args = parse(&["run", "login", "--param", "pw=a=b", "--param=user="]).unwrap();
+        let Some(Subcommand::Run { task, params, .. }) = args.command else {
+            panic!("not a run: {:?}", args.command);
+        };
+        assert_eq!(task, "login");
+        let params: Vec<(&str, &str)> = params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(params, [("pw", "a=b"), ("user", "")]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    forge::{RemoteRepo, RepoListing},
+    tasks::{shell_quote, Step},
+};
+
+/// Repositories of the forge that can be selected for cloning
+#[derive(Default)]
+pub struct CloneList {
+    pub repos: Vec<RemoteRepo>,
+    pub marked: Vec<bool>, // repositories that are selected for cloning
+    pub selected: usize,   // highlighted repository
+    pub loading: bool,     // the repositories are fetched
+    pub fetch: bool,       // the repositories should be fetched (again)
+    pub error: Option<String>,
+    pub skipped: Vec<String>, // entries of the response that are not a valid repository
+}
+
+impl CloneList {
+    pub fn next(&mut self) {
+        if !self.repos.is_empty() {
+            self.selected = (self.selected + 1) % self.repos.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.repos.is_empty() {
+            self.selected = (self.selected + self.repos.len() - 1) % self.repos.len();
+        }
+    }
+
+    /// Select or deselect the highlighted repository. Cloned repositories can not be selected.
+    pub fn toggle(&mut self) {
+        if let Some(repo) = self.repos.get(self.selected) {
+            if !repo.cloned {
+                self.marked[self.selected] = !self.marked[self.selected];
+            }
+        }
+    }
+
+    /// Select all repositories that are not cloned yet or, if all of them are selected, none
+    pub fn toggle_all(&mut self) {
+        let all_marked = self
+            .repos
+            .iter()
+            .zip(&self.marked)
+            .all(|(repo, marked)| repo.cloned || *marked);
+        for (repo, marked) in self.repos.iter().zip(self.marked.iter_mut()) {
+            *marked = !repo.cloned && !all_marked;
+        }
+    }
+
+    /// Show the result of a fetch
+    pub fn update(&mut self, result: Result<RepoListing, String>) {
+        self.loading = false;
+        match result {
+            Ok(RepoListing { repos, skipped }) => {
+                self.marked = vec![false; repos.len()];
+                self.repos = repos;
+                self.skipped = skipped;
+                self.selected = self.selected.min(self.repos.len().saturating_sub(1));
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    /// Repositories that are selected for cloning
+    pub fn marked_repos(&self) -> Vec<&RemoteRepo> {
+        self.repos
+            .iter()
+            .zip(&self.marked)
+            .filter(|(_, marked)| **marked)
+            .map(|(repo, _)| repo)
+            .collect()
+    }
+}
+
+/// Step that clones the repository into a directory of its name. The options end before the url,
+/// so that a url that starts with a dash is not taken as option of git.
+pub fn clone_step(repo: &RemoteRepo) -> Step {
+    Step {
+        run: format!(
+            "git clone -- {} {}",
+            shell_quote(&repo.clone_url),
+            shell_quote(&repo.name)
+        ),
+        name: Some(format!("clone {}", repo.name)),
+        continue_on_error: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Arguments that git gets from the command of the step
+    fn git_args(step: &Step) -> Vec<String> {
+        let command = step.run.strip_prefix("git ").unwrap();
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!("printf '%s\\n' {}", command))
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn url_is_not_taken_as_option() {
+        let repo = RemoteRepo {
+            name: "-n repo".to_string(),
+            clone_url: "--upload-pack=touch /tmp/pwned".to_string(),
+            description: String::new(),
+            cloned: false,
+        };
+        assert_eq!(
+            git_args(&clone_step(&repo)),
+            ["clone", "--", "--upload-pack=touch /tmp/pwned", "-n repo"]
+        );
+    }
+
+    #[test]
+    fn only_marked_repos_are_cloned() {
+        let repo = |name: &str, cloned| RemoteRepo {
+            name: name.to_string(),
+            clone_url: format!("https://example.com/{}.git", name),
+            description: String::new(),
+            cloned,
+        };
+        let mut list = CloneList::default();
+        list.update(Ok(RepoListing {
+            repos: vec![repo("a", false), repo("b", true), repo("c", false)],
+            skipped: Vec::new(),
+        }));
+        list.toggle_all();
+        let marked: Vec<&str> = list
+            .marked_repos()
+            .iter()
+            .map(|repo| repo.name.as_str())
+            .collect();
+        assert_eq!(marked, ["a", "c"]);
+    }
+}
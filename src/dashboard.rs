@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use crate::git::RepoStatus;
+
+// how often the repositories are scanned while the dashboard is shown
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// State of the git dashboard, the repositories are scanned in the background
+#[derive(Default)]
+pub struct Dashboard {
+    pub repos: Vec<RepoStatus>,
+    pub selected: usize,
+    pub scanning: bool,             // a scan is running
+    pub last_scan: Option<Instant>, // time the last scan was finished
+    // a refresh was requested, possibly while a scan was running whose result may be outdated
+    refresh_pending: bool,
+}
+
+impl Dashboard {
+    pub fn next(&mut self) {
+        if !self.repos.is_empty() {
+            self.selected = (self.selected + 1) % self.repos.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.repos.is_empty() {
+            self.selected = (self.selected + self.repos.len() - 1) % self.repos.len();
+        }
+    }
+
+    /// Returns true if a new scan should be started
+    pub fn needs_refresh(&self) -> bool {
+        !self.scanning
+            && (self.refresh_pending
+                || self
+                    .last_scan
+                    .is_none_or(|last_scan| last_scan.elapsed() >= REFRESH_INTERVAL))
+    }
+
+    /// Scan again as soon as possible. A running scan is finished first, then the next one starts.
+    pub fn refresh(&mut self) {
+        self.refresh_pending = true;
+    }
+
+    /// A scan was started
+    pub fn start_scan(&mut self) {
+        self.scanning = true;
+        self.refresh_pending = false;
+    }
+
+    /// Show the result of a scan. The highlighted repository stays highlighted if it still exists.
+    pub fn update(&mut self, repos: Vec<RepoStatus>) {
+        let selected = self.repos.get(self.selected).map(|repo| repo.path.clone());
+        self.repos = repos;
+        self.selected = selected
+            .and_then(|path| self.repos.iter().position(|repo| repo.path == path))
+            .unwrap_or(0);
+        self.scanning = false;
+        self.last_scan = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn repos(names: &[&str]) -> Vec<RepoStatus> {
+        names
+            .iter()
+            .map(|name| RepoStatus::new(PathBuf::from("/src").join(name)))
+            .collect()
+    }
+
+    #[test]
+    fn scans_are_repeated_after_the_interval() {
+        let mut dashboard = Dashboard::default();
+        assert!(dashboard.needs_refresh());
+        dashboard.start_scan();
+        assert!(!dashboard.needs_refresh());
+        dashboard.update(repos(&["a"]));
+        assert!(!dashboard.needs_refresh());
+        dashboard.last_scan = Some(Instant::now() - REFRESH_INTERVAL);
+        assert!(dashboard.needs_refresh());
+    }
+
+    #[test]
+    fn refresh_during_a_scan_is_kept() {
+        let mut dashboard = Dashboard::default();
+        dashboard.start_scan();
+        dashboard.refresh();
+        // the running scan is not started twice
+        assert!(!dashboard.needs_refresh());
+        dashboard.update(repos(&["a"]));
+        assert!(dashboard.needs_refresh());
+        dashboard.start_scan();
+        dashboard.update(repos(&["a"]));
+        assert!(!dashboard.needs_refresh());
+    }
+
+    #[test]
+    fn selection_follows_the_repository() {
+        let mut dashboard = Dashboard::default();
+        dashboard.update(repos(&["a", "b", "c"]));
+        dashboard.next();
+        dashboard.next();
+        dashboard.update(repos(&["0", "a", "c"]));
+        assert_eq!(dashboard.repos[dashboard.selected].name, "c");
+        dashboard.update(repos(&["a", "b"]));
+        assert_eq!(dashboard.selected, 0);
+        dashboard.previous();
+        assert_eq!(dashboard.repos[dashboard.selected].name, "b");
+    }
+}
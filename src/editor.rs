@@ -0,0 +1,152 @@
+/// Simple multi-line text editor, e.g. for commit messages. The cursor position is counted in
+/// characters.
+#[derive(Debug, Clone)]
+pub struct TextEditor {
+    pub lines: Vec<String>,
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Default for TextEditor {
+    fn default() -> Self {
+        TextEditor {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+        }
+    }
+}
+
+impl TextEditor {
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.trim().is_empty())
+    }
+
+    /// Byte index of the cursor in the current line
+    fn byte_index(&self) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices()
+            .nth(self.col)
+            .map_or(line.len(), |(i, _)| i)
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        self.lines[row].chars().count()
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        let i = self.byte_index();
+        self.lines[self.row].insert(i, c);
+        self.col += 1;
+    }
+
+    /// Split the line at the cursor
+    pub fn newline(&mut self) {
+        let i = self.byte_index();
+        let rest = self.lines[self.row].split_off(i);
+        self.row += 1;
+        self.lines.insert(self.row, rest);
+        self.col = 0;
+    }
+
+    /// Delete the character before the cursor, at the start of a line join it with the previous one
+    pub fn backspace(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            let i = self.byte_index();
+            self.lines[self.row].remove(i);
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    pub fn right(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    pub fn down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.col = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.col = self.line_len(self.row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> TextEditor {
+        let mut editor = TextEditor::default();
+        for c in text.chars() {
+            match c {
+                '\n' => editor.newline(),
+                c => editor.insert_char(c),
+            }
+        }
+        editor
+    }
+
+    #[test]
+    fn lines_are_split_and_joined_at_the_cursor() {
+        let mut editor = editor("héllo");
+        editor.left();
+        editor.left();
+        editor.newline();
+        assert_eq!(editor.lines, ["hél", "lo"]);
+        assert_eq!((editor.row, editor.col), (1, 0));
+        editor.backspace();
+        assert_eq!(editor.text(), "héllo");
+        assert_eq!((editor.row, editor.col), (0, 3));
+    }
+
+    #[test]
+    fn cursor_stays_inside_the_lines() {
+        let mut editor = editor("long line\nab");
+        editor.up();
+        assert_eq!((editor.row, editor.col), (0, 2));
+        editor.end();
+        editor.down();
+        assert_eq!((editor.row, editor.col), (1, 2));
+        editor.right();
+        assert_eq!((editor.row, editor.col), (1, 2));
+        assert!(!editor.is_empty());
+        assert!(TextEditor::default().is_empty());
+    }
+}
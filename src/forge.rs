@@ -0,0 +1,372 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::mpsc::Sender;
+
+// repositories that are requested per page
+const PAGE_SIZE: usize = 50;
+// stop paginating at some point if the server always announces another page
+const MAX_PAGES: usize = 100;
+
+/// API of the git forge. Forgejo uses the API of Gitea.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    #[default]
+    Github,
+    #[serde(alias = "forgejo")]
+    Gitea,
+    Gitlab,
+}
+
+impl ForgeKind {
+    /// Name of the kind as it is set with `type` in the config
+    pub fn name(self) -> &'static str {
+        match self {
+            ForgeKind::Github => "github",
+            ForgeKind::Gitea => "gitea",
+            ForgeKind::Gitlab => "gitlab",
+        }
+    }
+}
+
+/// `[forge]` table of the config
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ForgeConfig {
+    #[serde(default, rename = "type")]
+    pub kind: ForgeKind,
+    // base url of the API, e.g. https://codeberg.org/api/v1. Required for Gitea.
+    #[serde(default)]
+    pub url: Option<String>,
+    // user whose repositories are listed, `name` of the config if not set
+    #[serde(default)]
+    pub user: Option<String>,
+    // access token, necessary to see private repositories. On GitHub the repositories of the
+    // owner of the token are listed, `user` is not used then.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl ForgeConfig {
+    /// Base url of the API, the public instance if none is configured
+    pub fn base_url(&self) -> Option<String> {
+        let url = match (&self.url, self.kind) {
+            (Some(url), _) => url.as_str(),
+            (None, ForgeKind::Github) => "https://api.github.com",
+            (None, ForgeKind::Gitlab) => "https://gitlab.com/api/v4",
+            (None, ForgeKind::Gitea) => return None,
+        };
+        Some(url.trim_end_matches('/').to_string())
+    }
+}
+
+/// Repository of the user at the forge
+#[derive(Debug, Clone)]
+pub struct RemoteRepo {
+    pub name: String,
+    pub clone_url: String,
+    pub description: String,
+    pub cloned: bool, // a directory of the same name exists in git_repo_dir
+}
+
+/// Repositories of a listing and the entries of the response that could not be read
+#[derive(Debug, Default)]
+pub struct RepoListing {
+    pub repos: Vec<RemoteRepo>,
+    pub skipped: Vec<String>, // why an entry was skipped
+}
+
+/// Fetch all repositories of the user. Pages are requested until the server does not announce a
+/// next page (Link header) or, if it does not send Link headers, until a page is empty. Entries
+/// that are not a valid repository are skipped.
+pub fn list_repos(
+    config: &ForgeConfig,
+    name: &str,
+    git_repo_dir: &Path,
+) -> anyhow::Result<RepoListing> {
+    let Some(base_url) = config.base_url() else {
+        bail!("forge.url is not set");
+    };
+    let user = config.user.as_deref().unwrap_or(name);
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+
+    let mut listing = RepoListing::default();
+    for page in 1..=MAX_PAGES {
+        let url = match config.kind {
+            // /users/{user}/repos only contains public repositories, even with a token
+            ForgeKind::Github if config.token.is_some() => format!(
+                "{}/user/repos?affiliation=owner&per_page={}&page={}",
+                base_url, PAGE_SIZE, page
+            ),
+            ForgeKind::Github => format!(
+                "{}/users/{}/repos?per_page={}&page={}",
+                base_url, user, PAGE_SIZE, page
+            ),
+            ForgeKind::Gitea => format!(
+                "{}/users/{}/repos?limit={}&page={}",
+                base_url, user, PAGE_SIZE, page
+            ),
+            ForgeKind::Gitlab => format!(
+                "{}/users/{}/projects?per_page={}&page={}",
+                base_url, user, PAGE_SIZE, page
+            ),
+        };
+        let mut request = agent.get(&url).set("Accept", "application/json");
+        if let Some(token) = &config.token {
+            request = match config.kind {
+                ForgeKind::Github => request.set("Authorization", &format!("Bearer {}", token)),
+                ForgeKind::Gitea => request.set("Authorization", &format!("token {}", token)),
+                ForgeKind::Gitlab => request.set("PRIVATE-TOKEN", token),
+            };
+        }
+        let response = request
+            .call()
+            .with_context(|| format!("failed to request {}", url))?;
+        let next_page = response
+            .header("link")
+            .map(|link| link.contains("rel=\"next\""));
+        let entries: Vec<Value> = response
+            .into_json()
+            .with_context(|| format!("invalid response of {}", url))?;
+        let empty = entries.is_empty();
+        for entry in entries {
+            match parse_repo(config.kind, &entry, git_repo_dir) {
+                Ok(repo) => listing.repos.push(repo),
+                Err(err) => listing.skipped.push(format!("{:#}", err)),
+            }
+        }
+        if !next_page.unwrap_or(!empty) {
+            break;
+        }
+    }
+    listing.repos.sort_by_key(|repo| repo.name.to_lowercase());
+    Ok(listing)
+}
+
+/// Fetch the repositories in the background and send the result
+pub fn spawn_list_repos(
+    config: ForgeConfig,
+    name: String,
+    git_repo_dir: PathBuf,
+    tx: Sender<Result<RepoListing, String>>,
+) {
+    tokio::task::spawn_blocking(move || {
+        let result = list_repos(&config, &name, &git_repo_dir).map_err(|err| format!("{:#}", err));
+        let _ = tx.blocking_send(result);
+    });
+}
+
+/// Read a repository of the JSON response
+fn parse_repo(kind: ForgeKind, entry: &Value, git_repo_dir: &Path) -> anyhow::Result<RemoteRepo> {
+    let field = |key: &str| entry.get(key).and_then(Value::as_str);
+    // GitLab calls the directory name `path`, `name` is only the display name
+    let (name, clone_url) = match kind {
+        ForgeKind::Github | ForgeKind::Gitea => (field("name"), field("clone_url")),
+        ForgeKind::Gitlab => (field("path"), field("http_url_to_repo")),
+    };
+    let (Some(name), Some(clone_url)) = (name, clone_url) else {
+        bail!(
+            "repository without name or clone url in the response: {}",
+            entry
+        );
+    };
+    // the name is used as directory in git_repo_dir
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        bail!("invalid repository name '{}' in the response", name);
+    }
+    Ok(RemoteRepo {
+        name: name.to_string(),
+        clone_url: clone_url.to_string(),
+        description: field("description").unwrap_or_default().to_string(),
+        cloned: git_repo_dir.join(name).exists(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+
+    /// Response of the mock server to a request path: the Link header and the JSON body
+    type Handler = fn(&str) -> (Option<String>, String);
+
+    /// Serve the responses of the handler on a local port. Returns the base url and the paths
+    /// that were requested.
+    fn serve(handler: Handler) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // skip the headers of the request
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap().to_string();
+                let (link, body) = handler(&path);
+                recorded.lock().unwrap().push(path);
+                let link = link.map_or(String::new(), |link| format!("Link: {}\r\n", link));
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                    body.len(),
+                    link,
+                    body
+                )
+                .unwrap();
+            }
+        });
+        (base_url, requests)
+    }
+
+    fn page(path: &str) -> usize {
+        let (_, query) = path.split_once('?').unwrap();
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("page="))
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    /// Two repositories per page in the format of every forge, three pages in total
+    fn entries(path: &str) -> String {
+        let entries: Vec<String> = match page(path) {
+            1 => vec!["a".into(), "b".into()],
+            2 => vec!["c".into(), "d".into()],
+            3 => vec!["e".into()],
+            _ => vec![],
+        };
+        let entries: Vec<Value> = entries
+            .into_iter()
+            .map(|name| {
+                let url = format!("https://example.com/{}.git", name);
+                serde_json::json!({
+                    "name": name, "clone_url": url,
+                    "path": name, "http_url_to_repo": url,
+                })
+            })
+            .collect();
+        Value::Array(entries).to_string()
+    }
+
+    /// The last page links to the previous one only, like GitHub and GitLab do
+    fn with_links(path: &str) -> (Option<String>, String) {
+        let page = page(path);
+        let link = if page < 3 {
+            format!("<http://localhost/x?page={}>; rel=\"next\"", page + 1)
+        } else {
+            format!("<http://localhost/x?page={}>; rel=\"prev\"", page - 1)
+        };
+        (Some(link), entries(path))
+    }
+
+    fn without_links(path: &str) -> (Option<String>, String) {
+        (None, entries(path))
+    }
+
+    fn config(kind: ForgeKind, url: String) -> ForgeConfig {
+        ForgeConfig {
+            kind,
+            url: Some(url),
+            ..ForgeConfig::default()
+        }
+    }
+
+    fn names(listing: &RepoListing) -> Vec<&str> {
+        listing
+            .repos
+            .iter()
+            .map(|repo| repo.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn pages_are_followed_until_no_next_link() {
+        for kind in [ForgeKind::Github, ForgeKind::Gitea, ForgeKind::Gitlab] {
+            let (url, requests) = serve(with_links);
+            let listing = list_repos(&config(kind, url), "me", Path::new("/nonexistent")).unwrap();
+            assert_eq!(names(&listing), ["a", "b", "c", "d", "e"], "{:?}", kind);
+            assert_eq!(requests.lock().unwrap().len(), 3, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn pages_are_requested_until_one_is_empty() {
+        for kind in [ForgeKind::Github, ForgeKind::Gitea, ForgeKind::Gitlab] {
+            let (url, requests) = serve(without_links);
+            let listing = list_repos(&config(kind, url), "me", Path::new("/nonexistent")).unwrap();
+            assert_eq!(names(&listing), ["a", "b", "c", "d", "e"], "{:?}", kind);
+            let pages: Vec<usize> = requests.lock().unwrap().iter().map(|p| page(p)).collect();
+            assert_eq!(pages, [1, 2, 3, 4], "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn requests_use_the_api_of_the_forge() {
+        let cases = [
+            (
+                ForgeKind::Github,
+                None,
+                "/users/me/repos?per_page=50&page=1",
+            ),
+            (
+                ForgeKind::Github,
+                Some("secret"),
+                "/user/repos?affiliation=owner&per_page=50&page=1",
+            ),
+            (ForgeKind::Gitea, None, "/users/me/repos?limit=50&page=1"),
+            (
+                ForgeKind::Gitlab,
+                None,
+                "/users/me/projects?per_page=50&page=1",
+            ),
+        ];
+        for (kind, token, expected) in cases {
+            let (url, requests) = serve(|_| (None, "[]".to_string()));
+            let mut config = config(kind, url);
+            config.token = token.map(str::to_string);
+            list_repos(&config, "me", Path::new("/nonexistent")).unwrap();
+            assert_eq!(requests.lock().unwrap()[0], expected);
+        }
+    }
+
+    #[test]
+    fn invalid_entries_are_skipped() {
+        let (url, _) = serve(|path| {
+            let body = if page(path) == 1 {
+                r#"[{"name": "ok", "clone_url": "u"}, {"name": "../x", "clone_url": "u"}, {"id": 1}]"#
+            } else {
+                "[]"
+            };
+            (None, body.to_string())
+        });
+        let listing = list_repos(
+            &config(ForgeKind::Gitea, url),
+            "me",
+            Path::new("/nonexistent"),
+        )
+        .unwrap();
+        assert_eq!(names(&listing), ["ok"]);
+        assert_eq!(listing.skipped.len(), 2);
+    }
+}
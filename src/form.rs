@@ -0,0 +1,119 @@
+use zeroize::Zeroizing;
+
+use crate::{
+    secret::{pop_wiped, push_wiped},
+    tasks::{ParamKind, ParamValues, Task},
+};
+
+/// Form that collects the parameters of a task before it is run
+pub struct Form {
+    pub task: Task,
+    // entered value of every parameter of the task, a password is wiped when it is changed or the
+    // form is dropped
+    pub values: Vec<Zeroizing<String>>,
+    pub errors: Vec<Option<String>>, // validation error of every parameter
+    pub focused: usize,
+}
+
+impl Form {
+    pub fn new(task: Task) -> Form {
+        let values: Vec<Zeroizing<String>> = task
+            .params
+            .iter()
+            .map(|p| Zeroizing::new(p.default_value()))
+            .collect();
+        let errors = vec![None; values.len()];
+        Form {
+            task,
+            values,
+            errors,
+            focused: 0,
+        }
+    }
+
+    pub fn next(&mut self) {
+        if !self.values.is_empty() {
+            self.focused = (self.focused + 1) % self.values.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.values.is_empty() {
+            self.focused = (self.focused + self.values.len() - 1) % self.values.len();
+        }
+    }
+
+    /// Type a character into the focused field. Booleans are toggled with space, choices can
+    /// only be changed with left/right.
+    pub fn input_char(&mut self, c: char) {
+        let Some(param) = self.task.params.get(self.focused) else {
+            return;
+        };
+        match param.kind {
+            ParamKind::Boolean if c == ' ' => self.change(1),
+            ParamKind::Boolean | ParamKind::Choice { .. } => {}
+            ParamKind::Integer { .. } if !(c.is_ascii_digit() || c == '-') => {}
+            _ => push_wiped(&mut self.values[self.focused], c),
+        }
+        self.errors[self.focused] = None;
+    }
+
+    pub fn backspace(&mut self) {
+        let Some(param) = self.task.params.get(self.focused) else {
+            return;
+        };
+        if !matches!(param.kind, ParamKind::Boolean | ParamKind::Choice { .. }) {
+            pop_wiped(&mut self.values[self.focused]);
+            self.errors[self.focused] = None;
+        }
+    }
+
+    /// Toggle a boolean or select the next (1) or previous (-1) entry of a choice list
+    pub fn change(&mut self, direction: isize) {
+        let Some(param) = self.task.params.get(self.focused) else {
+            return;
+        };
+        let value = &mut self.values[self.focused];
+        match &param.kind {
+            ParamKind::Boolean => {
+                let toggled = if value.as_str() == "true" {
+                    "false"
+                } else {
+                    "true"
+                };
+                *value = Zeroizing::new(toggled.to_string());
+            }
+            ParamKind::Choice { choices } if !choices.is_empty() => {
+                let current = choices
+                    .iter()
+                    .position(|c| c == value.as_str())
+                    .unwrap_or(0) as isize;
+                let next = (current + direction).rem_euclid(choices.len() as isize);
+                *value = Zeroizing::new(choices[next as usize].clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Validate all fields. Returns the name and value of every parameter if all are valid,
+    /// otherwise the errors are stored and the first invalid field is focused.
+    pub fn submit(&mut self) -> Option<ParamValues> {
+        let mut values = Vec::new();
+        for (i, param) in self.task.params.iter().enumerate() {
+            match param.validate(&self.values[i]) {
+                Ok(value) => {
+                    self.errors[i] = None;
+                    values.push((param.name.clone(), Zeroizing::new(value)));
+                }
+                Err(err) => self.errors[i] = Some(err),
+            }
+        }
+        match self.errors.iter().position(Option::is_some) {
+            Some(invalid) => {
+                self.focused = invalid;
+                None
+            }
+            None => Some(values),
+        }
+    }
+}
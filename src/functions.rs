@@ -1,95 +1,1335 @@
-use std::{env::Args, fs::read_to_string, process::Stdio, sync::Arc};
+use std::{
+    convert::Infallible,
+    fmt,
+    fs::read_to_string,
+    future::Future,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    process::{ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
 
-use anyhow::Ok;
+use anyhow::{Context, Ok};
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::{setsid, Pid},
+};
+use serde::Serialize;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
     process::Command,
     sync::{
         mpsc::{Receiver, Sender},
-        Mutex,
+        watch,
     },
     task::JoinHandle,
+    time::{sleep, timeout},
 };
+use zeroize::Zeroizing;
 
-use crate::app::Config;
+use crate::{
+    app::Config,
+    cli::CliArgs,
+    privilege::{password_prompt, Escalation},
+    pty::PtyMaster,
+    secret::Secret,
+    tasks::{RetryPolicy, Step, TaskCatalog},
+    validate::{check_config, parse_file, ConfigErrors, KeyLocations, Location, Problem},
+};
 
-// Function to spawn the bash command and send each line of output over the channel
-pub async fn run_command(
-    tx: Sender<String>,
-    rx: Arc<Mutex<Receiver<String>>>,
-    commands: Vec<String>,
+/// Output stream of the command a line was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+    // messages of linutil itself, e.g. about retries
+    Info,
+}
+
+/// Result of a finished command
+#[derive(Debug, Clone, Copy)]
+pub struct Completion {
+    pub code: Option<i32>, // exit code, None if the process was terminated by a signal
+    pub signal: Option<i32>, // signal that terminated the process
+    pub stopped_with: Option<i32>, // last signal that was sent to cancel the process or because of a timeout
+    pub timed_out: bool,
+    pub attempt: u32, // number of the attempt that produced this result, starting at 1
+    pub duration: Duration,
+}
+
+impl Completion {
+    pub fn success(&self) -> bool {
+        self.code == Some(0) && self.stopped_with.is_none()
+    }
+
+    /// Description of how the process ended, e.g. "exit code 1"
+    pub fn reason(&self) -> String {
+        let status = match (self.code, self.signal) {
+            (Some(code), _) => format!("exit code {}", code),
+            (None, Some(signal)) => format!("terminated by {}", signal_name(signal)),
+            (None, None) => "unknown exit status".to_string(),
+        };
+        let status = match self.stopped_with {
+            Some(signal) => format!("stopped with {}, {}", signal_name(signal), status),
+            None => status,
+        };
+        if self.timed_out {
+            format!("timed out, {}", status)
+        } else {
+            status
+        }
+    }
+}
+
+/// Name of a signal number, e.g. SIGTERM
+pub fn signal_name(signal: i32) -> String {
+    match Signal::try_from(signal) {
+        std::result::Result::Ok(signal) => signal.as_str().to_string(),
+        Err(_) => format!("signal {}", signal),
+    }
+}
+
+// time a cancelled task gets to exit before the next, more forceful signal is sent
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+// character that ends the input of a terminal (VEOF)
+const CTRL_D: u8 = 0x04;
+
+/// Handle of a running task. Dropping the handle does not stop the task, it has to be cancelled.
+pub struct TaskHandle {
+    handle: JoinHandle<()>,
+    cancel: watch::Sender<bool>,
+}
+
+impl TaskHandle {
+    /// Run the steps in the background. Errors that prevent the command from running are
+    /// reported as output to the UI.
+    pub fn spawn(
+        tx: Sender<OutputMessage>,
+        rx: Receiver<InputMessage>,
+        steps: Vec<Step>,
+        options: RunOptions,
+    ) -> TaskHandle {
+        let (cancel, cancel_rx) = watch::channel(false);
+        let handle = tokio::spawn(async move {
+            let result = run_task(tx.clone(), rx, steps, options, cancel_rx).await;
+            if let Err(err) = result {
+                let _ = tx
+                    .send(OutputMessage::Line(
+                        Stream::Stderr,
+                        format!("Error: {:#}", err),
+                    ))
+                    .await;
+            }
+        });
+        TaskHandle { handle, cancel }
+    }
+
+    /// Terminate the process group of the task: SIGINT first, SIGTERM and SIGKILL if the
+    /// processes are still running after the grace period.
+    pub fn cancel(&self) {
+        let _ = self.cancel.send(true);
+    }
+
+    /// Cancel the task and wait until all of its processes are terminated
+    pub async fn shutdown(self) {
+        self.cancel();
+        let _ = self.handle.await;
+    }
+}
+
+/// Settings of a task that define how its commands are run
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    pub pty: bool,
+    pub working_dir: Option<PathBuf>,
+    pub timeout: Option<Duration>,
+    pub retry: RetryPolicy,
+    pub root: Option<Escalation>, // program that runs the commands as root
+    // sudo requests the password with a PasswordRequested message instead of prompting on the
+    // terminal
+    pub ask_password: bool,
+    // environment variables of the commands, e.g. the values of the task parameters. They are
+    // wiped from memory with the options and never shown by Debug. The copy of the process
+    // environment that std makes for spawning is freed (but not wiped) right after the spawn.
+    pub envs: Vec<(String, Zeroizing<String>)>,
+    // names of the variables in `envs` that hold passwords, they never end up in a command line
+    pub secret_envs: Vec<String>,
+}
+
+impl fmt::Debug for RunOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let envs: Vec<String> = self
+            .envs
+            .iter()
+            .map(|(name, _)| format!("{}=***", name))
+            .collect();
+        f.debug_struct("RunOptions")
+            .field("pty", &self.pty)
+            .field("working_dir", &self.working_dir)
+            .field("timeout", &self.timeout)
+            .field("retry", &self.retry)
+            .field("root", &self.root)
+            .field("ask_password", &self.ask_password)
+            .field("envs", &envs)
+            .field("secret_envs", &self.secret_envs)
+            .finish()
+    }
+}
+
+/// Result of a single step of a task
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub name: String,
+    pub continue_on_error: bool,
+    pub completion: Option<Completion>, // None if the step was skipped
+}
+
+impl StepResult {
+    /// The step did not prevent the task from succeeding
+    pub fn ok(&self) -> bool {
+        match &self.completion {
+            Some(completion) => completion.success() || self.continue_on_error,
+            None => false,
+        }
+    }
+}
+
+/// Overall result of a task that is sent after the last step
+#[derive(Debug, Clone)]
+pub struct TaskSummary {
+    pub steps: Vec<StepResult>,
+    pub duration: Duration,
+    // the user cancelled the task, possibly in a step that is allowed to fail
+    pub cancelled: bool,
+}
+
+impl TaskSummary {
+    pub fn success(&self) -> bool {
+        !self.cancelled && self.steps.iter().all(StepResult::ok)
+    }
+
+    /// Index and result of the step that stopped the task
+    pub fn failed_step(&self) -> Option<(usize, &StepResult)> {
+        self.steps
+            .iter()
+            .enumerate()
+            .find(|(_, step)| step.completion.is_some() && !step.ok())
+    }
+
+    /// Number of steps that succeeded
+    pub fn succeeded(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.completion.is_some_and(|c| c.success()))
+            .count()
+    }
+}
+
+/// Run the steps of a task one after another. A failing step stops the task unless it is allowed
+/// to fail (`continue_on_error`). Every step is retried according to the retry policy. A summary
+/// of all steps is sent as Finished message at the end. The input is written to stdin of the
+/// process that is running when it arrives.
+pub async fn run_task(
+    tx: Sender<OutputMessage>,
+    mut rx: Receiver<InputMessage>,
+    steps: Vec<Step>,
+    options: RunOptions,
+    cancel: watch::Receiver<bool>,
 ) -> anyhow::Result<()> {
-    let joined_command = commands.join("; ");
-    // Run the Bash command
-    let mut cmd = Command::new("bash")
-        .arg("-c")
-        .arg(joined_command)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-    // Clone the receiver for input piping
-    // Use joinhandle so that we can close the receiver when the command-process ends.
-    let stdin_writer_handle: Option<JoinHandle<()>> = if let Some(mut stdin) = cmd.stdin.take() {
-        let rx_clone = Arc::clone(&rx);
-        Some(tokio::spawn(async move {
-            // lock the mutex so that this thread can write to it.
-            let mut guard = rx_clone.lock().await;
-            loop {
-                // get messages from the receiver
-                let line = guard.recv().await;
-                match line {
-                    Some(msg) => {
-                        // write the message to stdin and therefore to the cmd process
-                        let stdin_write_res = stdin.write_all(msg.as_bytes()).await;
-                        if stdin_write_res.is_err() {
-                            eprintln!("Error writing to channel: {:?}", stdin_write_res);
-                            break;
-                        }
-                        // flush the output stream
-                        if stdin.flush().await.is_err() {
-                            eprintln!("Error while flushing channel");
-                            break;
-                        }
-                    }
-                    _ => {
-                        eprintln!("Channel has been closed");
-                        break;
-                    }
-                }
+    let start = Instant::now();
+    let total = steps.len();
+    let mut results: Vec<StepResult> = steps
+        .iter()
+        .map(|step| StepResult {
+            name: step.display_name().to_string(),
+            continue_on_error: step.continue_on_error,
+            completion: None,
+        })
+        .collect();
+
+    for (index, step) in steps.into_iter().enumerate() {
+        tx.send(OutputMessage::StepStarted {
+            index,
+            total,
+            name: results[index].name.clone(),
+        })
+        .await?;
+        let completion = run_step(tx.clone(), &mut rx, step.run, &options, cancel.clone()).await?;
+        results[index].completion = Some(completion);
+        tx.send(OutputMessage::StepFinished { index, completion })
+            .await?;
+
+        let cancelled_by_user = *cancel.borrow();
+        if cancelled_by_user || !results[index].ok() {
+            break;
+        }
+    }
+
+    let cancelled = *cancel.borrow();
+    tx.send(OutputMessage::Finished(TaskSummary {
+        steps: results,
+        duration: start.elapsed(),
+        cancelled,
+    }))
+    .await?;
+    Ok(())
+}
+
+/// Run the command of a step and retry it according to the retry policy. Only the result of the
+/// last attempt is returned, failed attempts before are reported as info lines.
+async fn run_step(
+    tx: Sender<OutputMessage>,
+    rx: &mut Receiver<InputMessage>,
+    command: String,
+    options: &RunOptions,
+    mut cancel: watch::Receiver<bool>,
+) -> anyhow::Result<Completion> {
+    let max_attempts = options.retry.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        let mut completion =
+            run_command(tx.clone(), rx, command.clone(), options, cancel.clone()).await?;
+        completion.attempt = attempt;
+
+        // a task that was cancelled by the user is never retried
+        let cancelled_by_user = *cancel.borrow();
+        if completion.success()
+            || cancelled_by_user
+            || attempt >= max_attempts
+            || !options.retry.should_retry(&completion)
+        {
+            return Ok(completion);
+        }
+
+        let delay = options.retry.backoff_after(attempt);
+        tx.send(OutputMessage::Line(
+            Stream::Info,
+            format!(
+                "Attempt {}/{} failed ({}), retrying in {}s",
+                attempt,
+                max_attempts,
+                completion.reason(),
+                delay.as_secs()
+            ),
+        ))
+        .await?;
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = cancelled(&mut cancel) => {
+                // the retry was cancelled, the step is reported like a cancelled process
+                completion.stopped_with = Some(Signal::SIGINT as i32);
+                return Ok(completion);
             }
-        }))
+        }
+        attempt += 1;
+        tx.send(OutputMessage::Line(
+            Stream::Info,
+            format!("Attempt {}/{}", attempt, max_attempts),
+        ))
+        .await?;
+    }
+}
+
+/// Messages that are sent from the command to the UI
+#[derive(Debug, Clone)]
+pub enum OutputMessage {
+    Line(Stream, String),
+    // unfinished line, replaced by the next partial or complete line
+    Partial(Stream, String),
+    StepStarted {
+        index: usize,
+        total: usize,
+        name: String,
+    },
+    StepFinished {
+        index: usize,
+        completion: Completion,
+    },
+    Finished(TaskSummary),
+    // sudo waits for the password on stdin. Retry is set if the last password was wrong.
+    PasswordRequested {
+        retry: bool,
+    },
+}
+
+/// Messages that are sent from the UI to stdin of the running process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMessage {
+    // raw bytes, including control characters
+    Bytes(Vec<u8>),
+    // input of a masked prompt like a password, it is wiped after it was written
+    Secret(Secret),
+    // end of the input: stdin of the process is closed, a pseudo-terminal receives Ctrl+D
+    Eof,
+}
+
+// Function to spawn the bash command and send each line of output over the channel.
+// If `pty` is set, the command is run inside a pseudo-terminal so that interactive programs
+// (sudo, credential prompts, progress bars) behave like in a regular terminal. The process group
+// of the command is terminated if the task is cancelled or the timeout is reached. The input of
+// the receiver is written to stdin of the command while it runs.
+pub async fn run_command(
+    tx: Sender<OutputMessage>,
+    rx: &mut Receiver<InputMessage>,
+    shell_command: String,
+    options: &RunOptions,
+    mut cancel: watch::Receiver<bool>,
+) -> anyhow::Result<Completion> {
+    let start = Instant::now();
+    // Run the Bash command, as root if the task requires it
+    let prompt =
+        (options.root == Some(Escalation::Sudo) && options.ask_password).then(password_prompt);
+    let mut command = match options.root {
+        Some(tool) => {
+            let mut command = Command::new(tool.program());
+            let envs: Vec<(&str, Option<&str>)> = options
+                .envs
+                .iter()
+                .map(|(name, value)| {
+                    let secret = options.secret_envs.contains(name);
+                    (name.as_str(), (!secret).then_some(value.as_str()))
+                })
+                .collect();
+            let dir = options.working_dir.as_deref();
+            command.args(tool.args(&shell_command, prompt.as_deref(), &envs, dir));
+            command
+        }
+        None => {
+            let mut command = Command::new("bash");
+            command.arg("-c").arg(shell_command);
+            command
+        }
+    };
+    command.envs(
+        options
+            .envs
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+    if let Some(dir) = &options.working_dir {
+        command.current_dir(dir);
+    }
+    let prompt = PromptDetector::new(prompt);
+
+    // stdin of the command, it is owned by the input forwarding and closed with it
+    let stdin: Box<dyn AsyncWrite + Unpin + Send>;
+    let reader_handles: Vec<JoinHandle<anyhow::Result<()>>>;
+    let mut cmd;
+    if options.pty || options.root.is_some_and(Escalation::needs_terminal) {
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+        let (master, slave) = PtyMaster::open(rows, cols)?;
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // the child has to run in its own session with the pty as controlling terminal, otherwise
+        // programs like sudo do not read from it
+        // SAFETY: the closure runs between fork and exec, it only calls setsid and ioctl, which
+        // are async-signal-safe, and neither allocates nor takes locks
+        unsafe {
+            command.pre_exec(|| {
+                setsid()?;
+                if nix::libc::ioctl(0, nix::libc::TIOCSCTTY, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                std::io::Result::Ok(())
+            });
+        }
+        cmd = command.spawn()?;
+        // drop the command to close our copy of the slave. Otherwise reading from the master never
+        // ends. It also frees the copy of the environment variables.
+        drop(command);
+
+        stdin = Box::new(master.clone());
+        reader_handles = vec![read_terminal(master, tx.clone(), prompt)];
     } else {
-        None
+        // run the command in its own session, and therefore process group, so that it can be
+        // terminated together with all processes it started. Without a controlling terminal a
+        // program that opens /dev/tty gets an error instead of being stopped by SIGTTIN.
+        // SAFETY: the closure runs between fork and exec, setsid is async-signal-safe and the errno
+        // is converted without allocating
+        unsafe {
+            command.pre_exec(|| {
+                setsid()?;
+                std::io::Result::Ok(())
+            });
+        }
+        cmd = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // the command holds a copy of the environment variables, which may contain passwords
+        drop(command);
+
+        stdin = Box::new(
+            cmd.stdin
+                .take()
+                .context("stdin of the command is not piped")?,
+        );
+        let stdout = cmd
+            .stdout
+            .take()
+            .context("stdout of the command is not piped")?;
+        let stderr = cmd
+            .stderr
+            .take()
+            .context("stderr of the command is not piped")?;
+        // both streams are read in their own thread so that they are forwarded as soon as a line
+        // is available
+        reader_handles = vec![
+            read_lines(
+                stdout,
+                Stream::Stdout,
+                tx.clone(),
+                PromptDetector::new(None),
+            ),
+            // sudo prints its prompt on stderr
+            read_lines(stderr, Stream::Stderr, tx.clone(), prompt),
+        ];
+    }
+
+    // the child is the leader of its process group (or session in case of a pty)
+    let process_group =
+        Pid::from_raw(cmd.id().context("command exited before it was tracked")? as i32);
+
+    let run = async {
+        for handle in reader_handles {
+            handle.await??;
+        }
+        // Ensure the command completes
+        anyhow::Ok(cmd.wait().await?)
+    };
+    tokio::pin!(run);
+
+    let deadline = async {
+        match options.timeout {
+            Some(duration) => sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let mut stopped_with = None;
+    let mut timed_out = false;
+    // the input is forwarded until one of the other branches completes, stdin is closed then
+    let status = tokio::select! {
+        status = &mut run => status?,
+        _ = cancelled(&mut cancel) => terminate(process_group, run, &mut stopped_with).await?,
+        _ = deadline => {
+            timed_out = true;
+            terminate(process_group, run, &mut stopped_with).await?
+        }
+        never = forward_input(rx, stdin, options.pty) => match never {},
     };
 
-    // Check if we can capture the stdout
-    if let Some(stdout) = cmd.stdout.take() {
-        let reader = BufReader::new(stdout);
+    Ok(Completion {
+        code: status.code(),
+        signal: status.signal(),
+        stopped_with,
+        timed_out,
+        attempt: 1,
+        duration: start.elapsed(),
+    })
+}
+
+/// Ask the processes of the group to stop and get more forceful if they do not react in time.
+/// Returns the exit status of the command, the last sent signal is stored in `stopped_with`.
+async fn terminate<F>(
+    process_group: Pid,
+    mut run: Pin<&mut F>,
+    stopped_with: &mut Option<i32>,
+) -> anyhow::Result<ExitStatus>
+where
+    F: Future<Output = anyhow::Result<ExitStatus>>,
+{
+    for signal in [Signal::SIGINT, Signal::SIGTERM, Signal::SIGKILL] {
+        let _ = killpg(process_group, signal);
+        *stopped_with = Some(signal as i32);
+        if let std::result::Result::Ok(result) = timeout(CANCEL_GRACE_PERIOD, run.as_mut()).await {
+            // background jobs of the shell ignore SIGINT and would outlive the command
+            let _ = killpg(process_group, Signal::SIGKILL);
+            return result;
+        }
+    }
+    run.await
+}
+
+/// Wait until the task is cancelled. If the handle of the task was dropped, it cannot be cancelled
+/// anymore and this never returns.
+async fn cancelled(cancel: &mut watch::Receiver<bool>) {
+    if cancel.wait_for(|cancelled| *cancelled).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Write the input of the receiver to stdin of the command. Input that arrives after stdin was
+/// closed is dropped. Never returns, the forwarding ends when the future is dropped together with
+/// stdin.
+async fn forward_input(
+    rx: &mut Receiver<InputMessage>,
+    stdin: Box<dyn AsyncWrite + Unpin + Send>,
+    pty: bool,
+) -> Infallible {
+    let mut stdin = Some(stdin);
+    loop {
+        // the input is wiped after it was written, it may contain a password
+        let data = match rx.recv().await {
+            Some(InputMessage::Bytes(bytes)) => Zeroizing::new(bytes),
+            Some(InputMessage::Secret(secret)) => secret.into_bytes(),
+            // the line discipline of the terminal turns Ctrl+D into the end of the input
+            Some(InputMessage::Eof) if pty => Zeroizing::new(vec![CTRL_D]),
+            // closing stdin signals the end of the input to the command
+            Some(InputMessage::Eof) => {
+                stdin = None;
+                continue;
+            }
+            // no more input, e.g. stdin of the headless mode reached its end
+            None => {
+                drop(stdin);
+                return std::future::pending().await;
+            }
+        };
+        let Some(writer) = stdin.as_mut() else {
+            continue;
+        };
+        // the command may have closed its stdin or exited already
+        if writer.write_all(&data).await.is_err() || writer.flush().await.is_err() {
+            stdin = None;
+        }
+    }
+}
+
+/// Recognizes the password prompt of sudo in the output of a command, so that it is answered
+/// instead of shown
+struct PromptDetector {
+    prompt: Option<String>,
+    requests: usize, // number of times the password was requested already
+}
 
-        // Send each line as it is available to the main thread
-        let mut lines = reader.lines();
+impl PromptDetector {
+    fn new(prompt: Option<String>) -> PromptDetector {
+        PromptDetector {
+            prompt,
+            requests: 0,
+        }
+    }
 
+    /// Message for the output line: a password request for the prompt, the line itself otherwise
+    fn message(&mut self, stream: Stream, line: String) -> OutputMessage {
+        if self.prompt.as_ref() != Some(&line) {
+            return OutputMessage::Line(stream, line);
+        }
+        // sudo asks again if the password was wrong
+        self.requests += 1;
+        OutputMessage::PasswordRequested {
+            retry: self.requests > 1,
+        }
+    }
+}
+
+/// Send every line of the reader to the UI
+fn read_lines<R>(
+    reader: R,
+    stream: Stream,
+    tx: Sender<OutputMessage>,
+    mut prompt: PromptDetector,
+) -> JoinHandle<anyhow::Result<()>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
         while let Some(line) = lines.next_line().await? {
-            // send every output to the main process
-            tx.send(line).await?;
+            tx.send(prompt.message(stream, line)).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Send the output of a pseudo-terminal to the UI. Interactive programs print prompts without a
+/// trailing newline, therefore unfinished lines are sent as partial lines.
+fn read_terminal(
+    mut master: PtyMaster,
+    tx: Sender<OutputMessage>,
+    mut prompt: PromptDetector,
+) -> JoinHandle<anyhow::Result<()>> {
+    tokio::spawn(async move {
+        let mut buffer = [0u8; 4096];
+        let mut pending: Vec<u8> = Vec::new();
+        loop {
+            let len = master.read(&mut buffer).await?;
+            if len == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buffer[..len]);
+            while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end_matches(['\n', '\r']).to_string();
+                tx.send(prompt.message(Stream::Stdout, line)).await?;
+            }
+            if !pending.is_empty() {
+                let partial = String::from_utf8_lossy(&pending).to_string();
+                tx.send(OutputMessage::Partial(Stream::Stdout, partial))
+                    .await?;
+            }
         }
+        if !pending.is_empty() {
+            let line = String::from_utf8_lossy(&pending).to_string();
+            tx.send(OutputMessage::Line(Stream::Stdout, line)).await?;
+        }
+        Ok(())
+    })
+}
+
+// name of the directory below /etc and the user config directory
+const CONFIG_DIR_NAME: &str = "linutil_rs";
+
+/// Directories that can contain the configuration and the task catalog, ordered from the lowest
+/// to the highest priority: /etc/linutil_rs and $XDG_CONFIG_HOME/linutil_rs (~/.config/linutil_rs
+/// if XDG_CONFIG_HOME is not set)
+pub fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![Path::new("/etc").join(CONFIG_DIR_NAME)];
+    let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(user_dir) = user_dir {
+        dirs.push(user_dir.join(CONFIG_DIR_NAME));
     }
+    dirs
+}
 
-    // Ensure the command completes
-    cmd.wait().await?;
-    // after the command completed, close the thread that handles the transfer of messages to stdin
-    // of the now closed process
-    if let Some(handle) = stdin_writer_handle {
-        handle.abort();
-        let _ = handle.await;
+/// Config files that are read, including the ones that do not exist (yet)
+pub fn config_files(args: &CliArgs) -> Vec<PathBuf> {
+    match &args.config_file {
+        Some(file) => vec![PathBuf::from(file)],
+        None => config_dirs()
+            .into_iter()
+            .map(|dir| dir.join("config.toml"))
+            .collect(),
     }
-    Ok(())
 }
 
-pub fn read_config(file_path: &str, args: &Args) -> anyhow::Result<Config> {
-    let file_content = read_to_string(file_path)?;
-    let mut config: Config = toml::from_str(&file_content)?;
-    Ok(config)
+/// Read and validate the configuration. The config.toml files of all config directories are
+/// merged, keys of the user config take precedence over the system config. If a config file is
+/// given on the command line, only this file is read. Keys set on the command line are applied
+/// last. Every problem that is found is returned with the file and position of its cause.
+pub fn read_config(args: &CliArgs) -> Result<Config, ConfigErrors> {
+    // a config file of the command line has to exist, the ones of the config directories not
+    let files: Vec<PathBuf> = config_files(args)
+        .into_iter()
+        .filter(|file| args.config_file.is_some() || file.exists())
+        .collect();
+
+    // all files are read even if one of them is broken so that the problems of all are reported
+    let mut problems = Vec::new();
+    let mut locations = KeyLocations::default();
+    let mut merged = toml::Table::new();
+    for file in &files {
+        let file_content = match read_to_string(file) {
+            std::result::Result::Ok(file_content) => file_content,
+            Err(err) => {
+                let location = Location::File {
+                    path: file.clone(),
+                    position: None,
+                };
+                problems.push(Problem::new(
+                    Some(location),
+                    format!("failed to read: {}", err),
+                ));
+                continue;
+            }
+        };
+        match parse_file(file, &file_content) {
+            std::result::Result::Ok(table) => {
+                locations.add_file(file, &file_content);
+                merge_tables(&mut merged, table);
+            }
+            Err(problem) => problems.push(problem),
+        }
+    }
+    // the values of a broken file are missing, checking them would only report follow-up errors
+    if !problems.is_empty() {
+        return Err(ConfigErrors(problems));
+    }
+    for (key, value) in &args.overrides {
+        locations.add_override(key);
+        set_key(&mut merged, key, parse_value(key, value));
+    }
+
+    check_config(merged, &locations)
+}
+
+/// Merge the keys of `other` into `base`. Tables are merged recursively, all other values of
+/// `other` replace the ones of `base`.
+fn merge_tables(base: &mut toml::Table, other: toml::Table) {
+    for (key, value) in other {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(other_table)) => {
+                merge_tables(base_table, other_table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Set a possibly dotted key (e.g. `forge.url`) in the table
+fn set_key(table: &mut toml::Table, key: &str, value: toml::Value) {
+    match key.split_once('.') {
+        Some((first, rest)) => {
+            let entry = table
+                .entry(first)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::Table::new());
+            }
+            if let toml::Value::Table(sub_table) = entry {
+                set_key(sub_table, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Keys of the config that hold strings. Their values are taken from the command line as they are,
+/// `--name 1234` or `--git-repo-dir 2024-01-01` must not become a number or a date.
+const STRING_KEYS: &[&str] = &[
+    "name",
+    "git_repo_dir",
+    "task_file",
+    "git_backend",
+    "forge.type",
+    "forge.url",
+    "forge.user",
+    "forge.token",
+];
+
+/// Interpret a value of the command line for the key. Values of string keys are strings, all
+/// others are read as toml value (number, boolean, array, ...) and as string if that fails.
+fn parse_value(key: &str, value: &str) -> toml::Value {
+    if STRING_KEYS.contains(&key) {
+        return toml::Value::String(value.to_string());
+    }
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+/// Path of the task catalog: the `task_file` of the config or the first tasks.toml that is found
+/// in the user config, the system config and the working directory
+pub fn task_file(config: &Config) -> PathBuf {
+    if let Some(file) = &config.task_file {
+        return PathBuf::from(expand_home(file));
+    }
+    config_dirs()
+        .into_iter()
+        .rev()
+        .map(|dir| dir.join("tasks.toml"))
+        .find(|file| file.exists())
+        .unwrap_or_else(|| PathBuf::from("tasks.toml"))
+}
+
+/// Replace a leading `~` with the home directory
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), std::env::var("HOME")) {
+        (Some(rest), std::result::Result::Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home, rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Read the task catalog that defines the entries of the start screen. A problem is reported
+/// with the file and position of its cause like the ones of the config.
+pub fn read_tasks(file_path: &Path) -> Result<TaskCatalog, Problem> {
+    let file_content = read_to_string(file_path).map_err(|err| {
+        let location = Location::File {
+            path: file_path.to_path_buf(),
+            position: None,
+        };
+        Problem::new(
+            Some(location),
+            format!("failed to read the task catalog: {}", err),
+        )
+    })?;
+    parse_file(file_path, &file_content)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn step(run: &str, continue_on_error: bool) -> Step {
+        Step {
+            run: run.to_string(),
+            name: None,
+            continue_on_error,
+        }
+    }
+
+    /// Start the steps in the background. Returns the handle of the task, the receiver of its
+    /// output and the sender of its input.
+    fn start(
+        steps: Vec<Step>,
+        options: RunOptions,
+    ) -> (
+        TaskHandle,
+        mpsc::Receiver<OutputMessage>,
+        mpsc::Sender<InputMessage>,
+    ) {
+        let (tx, rx) = mpsc::channel(100);
+        let (tx_input, rx_input) = mpsc::channel(5);
+        let task = TaskHandle::spawn(tx, rx_input, steps, options);
+        (task, rx, tx_input)
+    }
+
+    /// Run the command with the given input and collect everything it sent to the UI
+    async fn run_with_input(command: &str, pty: bool, input: &[&str]) -> Vec<OutputMessage> {
+        let options = RunOptions {
+            pty,
+            ..RunOptions::default()
+        };
+        let (_task, mut rx, tx_input) = start(vec![step(command, false)], options);
+        for line in input {
+            tx_input
+                .send(InputMessage::Bytes(line.as_bytes().to_vec()))
+                .await
+                .unwrap();
+        }
+        let mut messages = Vec::new();
+        while let Some(message) = rx.recv().await {
+            messages.push(message);
+        }
+        messages
+    }
+
+    async fn run(command: &str) -> Vec<OutputMessage> {
+        run_with_input(command, false, &[]).await
+    }
+
+    /// Complete lines that were sent to the UI
+    fn lines(messages: &[OutputMessage]) -> Vec<(Stream, &str)> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                OutputMessage::Line(stream, line) => Some((*stream, line.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Result of the first step of the task that sent the messages
+    fn completion(messages: &[OutputMessage]) -> Completion {
+        match messages.last() {
+            Some(OutputMessage::Finished(summary)) => summary.steps[0].completion.unwrap(),
+            _ => panic!("the last message is not the summary: {:?}", messages),
+        }
+    }
+
+    #[tokio::test]
+    async fn stderr_is_streamed_with_its_stream() {
+        let messages = run("echo out; echo err >&2").await;
+        let lines = lines(&messages);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.contains(&(Stream::Stdout, "out")));
+        assert!(lines.contains(&(Stream::Stderr, "err")));
+    }
+
+    #[tokio::test]
+    async fn exit_status_is_reported_last() {
+        let completion = completion(&run("echo done; exit 3").await);
+        assert_eq!(completion.code, Some(3));
+        assert_eq!(completion.signal, None);
+        assert!(!completion.success());
+    }
+
+    #[tokio::test]
+    async fn signal_of_a_killed_command_is_reported() {
+        let completion = completion(&run("kill -TERM $$").await);
+        assert_eq!(completion.code, None);
+        assert_eq!(completion.signal, Some(15));
+    }
+
+    #[tokio::test]
+    async fn pty_is_the_terminal_of_the_command() {
+        let command = "[ -t 0 ] && [ -t 1 ] && [ -t 2 ] && echo tty; tty > /dev/null && echo ctty";
+        let messages = run_with_input(command, true, &[]).await;
+        assert_eq!(
+            lines(&messages),
+            [(Stream::Stdout, "tty"), (Stream::Stdout, "ctty")]
+        );
+        let messages = run(command).await;
+        assert!(lines(&messages).is_empty());
+    }
+
+    #[tokio::test]
+    async fn pty_prompt_is_sent_as_partial_line() {
+        let options = RunOptions {
+            pty: true,
+            ..RunOptions::default()
+        };
+        let command = "printf 'Name: '; read name; echo \"hi $name\"";
+        let (_task, mut rx, tx_input) = start(vec![step(command, false)], options);
+        // the prompt has no newline, it is answered as soon as it is shown
+        let mut messages = Vec::new();
+        while let Some(message) = rx.recv().await {
+            if matches!(&message, OutputMessage::Partial(Stream::Stdout, prompt) if prompt == "Name: ")
+            {
+                let input = InputMessage::Bytes(b"bob\n".to_vec());
+                tx_input.send(input).await.unwrap();
+            }
+            messages.push(message);
+        }
+        assert_eq!(lines(&messages).last(), Some(&(Stream::Stdout, "hi bob")));
+        assert!(completion(&messages).success());
+    }
+
+    /// Run the steps and return the lines they printed and the summary of the task. The task is
+    /// cancelled as soon as a line contains `cancel_at`.
+    async fn run_steps(
+        steps: Vec<Step>,
+        options: RunOptions,
+        cancel_at: Option<&str>,
+    ) -> (Vec<(Stream, String)>, TaskSummary) {
+        let (task, mut rx, _tx_input) = start(steps, options);
+        let mut lines = Vec::new();
+        loop {
+            match rx.recv().await.unwrap() {
+                OutputMessage::Line(stream, line) => {
+                    if cancel_at.is_some_and(|cancel_at| line.contains(cancel_at)) {
+                        task.cancel();
+                    }
+                    lines.push((stream, line));
+                }
+                OutputMessage::Finished(summary) => return (lines, summary),
+                _ => {}
+            }
+        }
+    }
+
+    /// Like `run_steps` for a single command, returns the result of its last attempt
+    async fn run_with_options(
+        command: &str,
+        options: RunOptions,
+        cancel_at: Option<&str>,
+    ) -> (Vec<(Stream, String)>, Completion) {
+        let (lines, summary) = run_steps(vec![step(command, false)], options, cancel_at).await;
+        (lines, summary.steps[0].completion.unwrap())
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_step_that_may_fail_cancels_the_task() {
+        let steps = vec![step("echo started; sleep 5", true), step("true", false)];
+        let (_, summary) = run_steps(steps, RunOptions::default(), Some("started")).await;
+        assert!(summary.cancelled);
+        assert!(!summary.success());
+        assert!(summary.steps[1].completion.is_none());
+    }
+
+    /// True if the process exists and is not a zombie
+    fn alive(pid: &str) -> bool {
+        read_to_string(format!("/proc/{}/stat", pid))
+            .is_ok_and(|stat| !stat.rsplit(')').next().unwrap_or("").starts_with(" Z"))
+    }
+
+    #[tokio::test]
+    async fn cancel_terminates_the_process_group() {
+        // the inner shell is a grandchild, it prints its pid and waits
+        let command = "sh -c 'echo $$; exec sleep 30'; echo not cancelled";
+        let (lines, completion) = run_with_options(command, RunOptions::default(), Some("")).await;
+        assert_eq!(lines.len(), 1);
+        assert_eq!(completion.stopped_with, Some(Signal::SIGINT as i32));
+        assert!(!completion.success());
+        assert!(completion.duration < Duration::from_secs(30));
+        assert_terminated(&lines[0].1).await;
+    }
+
+    /// Wait until the process is gone, the signal may still be pending when the shell has exited
+    async fn assert_terminated(pid: &str) {
+        for _ in 0..100 {
+            if !alive(pid) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("process {} survived the cancellation", pid);
+    }
+
+    #[tokio::test]
+    async fn cancel_terminates_background_jobs() {
+        // background jobs of a shell without job control ignore SIGINT
+        let command = "sleep 30 > /dev/null 2>&1 & sleep 0.2; echo $!; wait";
+        let (lines, completion) = run_with_options(command, RunOptions::default(), Some("")).await;
+        assert_eq!(completion.stopped_with, Some(Signal::SIGINT as i32));
+        assert!(completion.duration < CANCEL_GRACE_PERIOD);
+        assert_terminated(&lines[0].1).await;
+    }
+
+    #[tokio::test]
+    async fn command_has_no_controlling_terminal() {
+        // the command leads a session of its own, so reading the terminal can not stop it
+        let command = "[ \"$(cut -d' ' -f6 /proc/$$/stat)\" = $$ ] && echo leader; \
+                       (: < /dev/tty) 2> /dev/null || echo no terminal";
+        let (lines, completion) = run_with_options(command, RunOptions::default(), None).await;
+        assert!(completion.success());
+        let output: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(output, ["leader", "no terminal"]);
+    }
+
+    #[tokio::test]
+    async fn timeout_stops_the_command() {
+        let options = RunOptions {
+            timeout: Some(Duration::from_millis(200)),
+            ..RunOptions::default()
+        };
+        let (_, completion) = run_with_options("sleep 5", options, None).await;
+        assert!(completion.timed_out);
+        assert_eq!(completion.stopped_with, Some(Signal::SIGINT as i32));
+        assert!(!completion.success());
+        assert!(completion.duration < Duration::from_secs(5));
+        assert!(completion.reason().starts_with("timed out"));
+    }
+
+    #[tokio::test]
+    async fn failed_attempts_are_retried() {
+        let options = RunOptions {
+            retry: RetryPolicy {
+                max_attempts: 3,
+                ..RetryPolicy::default()
+            },
+            ..RunOptions::default()
+        };
+        let (lines, completion) = run_with_options("exit 3", options, None).await;
+        let retries = lines
+            .iter()
+            .filter(|(stream, line)| *stream == Stream::Info && line.contains("retrying"))
+            .count();
+        assert_eq!(retries, 2);
+        assert_eq!(completion.attempt, 3);
+        assert_eq!(completion.code, Some(3));
+    }
+
+    #[tokio::test]
+    async fn cancelling_the_backoff_cancels_the_task() {
+        let options = RunOptions {
+            retry: RetryPolicy {
+                max_attempts: 3,
+                backoff: 60,
+                ..RetryPolicy::default()
+            },
+            ..RunOptions::default()
+        };
+        let (_, completion) = run_with_options("false", options, Some("retrying")).await;
+        assert_eq!(completion.attempt, 1);
+        assert!(completion.stopped_with.is_some());
+        assert!(!completion.success());
+    }
+
+    #[tokio::test]
+    async fn failed_step_stops_the_task() {
+        let steps = vec![
+            step("echo one", false),
+            step("exit 2", true),
+            step("exit 3", false),
+            step("echo four", false),
+        ];
+        let (lines, summary) = run_steps(steps, RunOptions::default(), None).await;
+        let output: Vec<&str> = lines
+            .iter()
+            .filter(|(stream, _)| *stream == Stream::Stdout)
+            .map(|(_, line)| line.as_str())
+            .collect();
+        assert_eq!(output, ["one"]);
+        assert!(!summary.success());
+        assert_eq!(summary.succeeded(), 1);
+        // the failure of the second step is allowed, the third one stops the task
+        let (index, failed) = summary.failed_step().unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(failed.completion.unwrap().code, Some(3));
+        assert!(summary.steps[3].completion.is_none());
+    }
+
+    #[test]
+    fn string_overrides_are_not_parsed_as_toml() {
+        let file =
+            std::env::temp_dir().join(format!("linutil_rs-overrides-{}.toml", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let overrides = [
+            ("name", "1234"),
+            ("git_repo_dir", "2024-01-01"),
+            ("forge.token", "true"),
+            ("scrollback", "500"),
+        ];
+        let args = CliArgs {
+            config_file: Some(file.display().to_string()),
+            overrides: overrides
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ..CliArgs::default()
+        };
+        let config = read_config(&args);
+        std::fs::remove_file(&file).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.name, "1234");
+        assert_eq!(config.git_repo_dir, "2024-01-01");
+        assert_eq!(config.forge.token.as_deref(), Some("true"));
+        assert_eq!(config.scrollback, 500);
+    }
+
+    #[test]
+    fn task_catalog_problems_have_a_position() {
+        let file =
+            std::env::temp_dir().join(format!("linutil_rs-tasks-{}.toml", std::process::id()));
+        let problem = |content: &str| {
+            std::fs::write(&file, content).unwrap();
+            read_tasks(&file).unwrap_err().to_string()
+        };
+        let syntax = problem("[[task]]\nname = \"a\"\nsteps = [\"ls\"\n");
+        let wrong_type = problem("[[task]]\nname = \"a\"\n  timeout = \"ten\"\n");
+        std::fs::remove_file(&file).unwrap();
+        let prefix = file.display().to_string();
+        assert!(
+            syntax.starts_with(&format!("{}:4:1: ", prefix)),
+            "{}",
+            syntax
+        );
+        assert!(
+            wrong_type.starts_with(&format!("{}:3:13: ", prefix)),
+            "{}",
+            wrong_type
+        );
+    }
+
+    #[test]
+    fn only_the_whole_prompt_line_requests_the_password() {
+        let prompt = password_prompt();
+        let mut detector = PromptDetector::new(Some(prompt.clone()));
+        let line = |line: &str| OutputMessage::Line(Stream::Stderr, line.to_string());
+        let message = |detector: &mut PromptDetector, text: &str| {
+            format!("{:?}", detector.message(Stream::Stderr, text.to_string()))
+        };
+        assert_eq!(
+            message(&mut detector, "output"),
+            format!("{:?}", line("output"))
+        );
+        assert_eq!(
+            message(&mut detector, &prompt),
+            format!("{:?}", OutputMessage::PasswordRequested { retry: false })
+        );
+        let mixed = format!("output {}", prompt);
+        assert_eq!(
+            message(&mut detector, &mixed),
+            format!("{:?}", line(&mixed))
+        );
+        assert_eq!(
+            message(&mut detector, &prompt),
+            format!("{:?}", OutputMessage::PasswordRequested { retry: true })
+        );
+        let mut without = PromptDetector::new(None);
+        assert_eq!(
+            message(&mut without, &prompt),
+            format!("{:?}", line(&prompt))
+        );
+    }
+
+    /// Acts like `sudo -S -p <prompt>`: prints the prompt on stderr in two writes between other
+    /// output and reads the password from stdin, until it is hunter2
+    const FAKE_SUDO: &str = r#"
+        printf 'before\n' >&2
+        while true; do
+            printf '%s' "${PROMPT:0:12}" >&2
+            sleep 0.1
+            printf '%s\nafter\n' "${PROMPT:12}" >&2
+            read -r password
+            [ "$password" = hunter2 ] && break
+        done
+        echo "password $password"
+        read -r -t 0.5 extra && echo "extra $extra"
+        exit 0
+    "#;
+
+    #[tokio::test]
+    async fn sudo_prompt_is_answered_once_per_request() {
+        let prompt = password_prompt();
+        let mut child = Command::new("bash")
+            .arg("-c")
+            .arg(FAKE_SUDO)
+            .env("PROMPT", &prompt)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (tx, mut rx) = mpsc::channel(16);
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        read_lines(
+            stdout,
+            Stream::Stdout,
+            tx.clone(),
+            PromptDetector::new(None),
+        );
+        read_lines(
+            stderr,
+            Stream::Stderr,
+            tx,
+            PromptDetector::new(Some(prompt.clone())),
+        );
+        let (input_tx, mut input_rx) = mpsc::channel(4);
+        let stdin: Box<dyn AsyncWrite + Unpin + Send> = Box::new(child.stdin.take().unwrap());
+        tokio::spawn(async move { forward_input(&mut input_rx, stdin, false).await });
+
+        // the first password is wrong, the retry is answered with the right one
+        let mut lines = Vec::new();
+        let mut requests = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            match msg {
+                OutputMessage::Line(_, line) => lines.push(line),
+                OutputMessage::PasswordRequested { retry } => {
+                    requests.push(retry);
+                    let password = if retry { "hunter2\n" } else { "wrong\n" };
+                    let secret = InputMessage::Secret(Secret::from(password));
+                    input_tx.send(secret).await.unwrap();
+                }
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+        assert!(child.wait().await.unwrap().success());
+        assert_eq!(requests, [false, true]);
+        let count = |text: &str| lines.iter().filter(|line| *line == text).count();
+        assert_eq!(count("before"), 1);
+        assert_eq!(count("after"), 2);
+        // the password was written once for every request and nothing more
+        assert_eq!(count("password hunter2"), 1);
+        assert!(!lines.iter().any(|line| line.starts_with("extra")));
+        assert!(!lines.iter().any(|line| line.contains(&prompt[12..])));
+    }
+
+    #[tokio::test]
+    async fn secret_input_is_written_to_stdin() {
+        let (writer, mut reader) = tokio::io::duplex(64);
+        let (tx, mut rx) = mpsc::channel(5);
+        tx.send(InputMessage::Secret(Secret::from("hunter2\n")))
+            .await
+            .unwrap();
+        tx.send(InputMessage::Eof).await.unwrap();
+        let mut received = Vec::new();
+        tokio::select! {
+            never = forward_input(&mut rx, Box::new(writer), false) => match never {},
+            result = reader.read_to_end(&mut received) => result.unwrap(),
+        };
+        assert_eq!(received, b"hunter2\n");
+    }
 }
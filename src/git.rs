@@ -0,0 +1,463 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use nix::unistd::setsid;
+use serde::Deserialize;
+use tokio::{
+    process::Command,
+    sync::{mpsc::Sender, Semaphore},
+    task::JoinSet,
+};
+
+use crate::git_native::NativeGit;
+
+// number of repositories whose status is read at the same time
+const MAX_PARALLEL_SCANS: usize = 8;
+
+/// State of a git repository as shown in the dashboard
+#[derive(Debug, Clone, Default)]
+pub struct RepoStatus {
+    pub name: String,
+    pub path: PathBuf,
+    pub branch: Option<String>, // None if HEAD is detached
+    // commits ahead and behind of the upstream branch, None if there is no upstream
+    pub ahead_behind: Option<(usize, usize)>,
+    pub changed: usize,              // files with staged or unstaged changes
+    pub untracked: usize,            // files that are not tracked yet
+    pub last_commit: Option<String>, // short hash, subject and age of the last commit
+    pub error: Option<String>,       // reason if the status could not be read
+}
+
+impl RepoStatus {
+    /// Status of the repository at the path without any information yet
+    pub fn new(path: PathBuf) -> RepoStatus {
+        RepoStatus {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path,
+            ..RepoStatus::default()
+        }
+    }
+}
+
+/// Changed file of a repository as shown in the push review
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: String,
+    pub old_path: Option<String>, // original path of a staged rename
+    pub staged: bool,             // the index contains changes of the file
+    pub unstaged: bool,           // the working tree contains changes that are not staged
+    pub untracked: bool,          // the file is not tracked yet
+}
+
+/// Read-only queries of the git screens. Commands that change a repository, like commit, fetch
+/// and push, always run git itself.
+pub trait GitBackend: Send + Sync {
+    /// Read branch, upstream distance, changed files and the last commit of the repository
+    fn repo_status(&self, path: PathBuf) -> RepoStatus;
+
+    /// Changed and untracked files of the repository
+    fn changed_files(&self, repo: &Path) -> Result<Vec<FileChange>, String>;
+
+    /// Staged and unstaged changes of the file. Untracked files are shown completely.
+    fn diff(&self, repo: &Path, file: &FileChange) -> Result<String, String>;
+}
+
+/// Implementation of the git queries, `git_backend` in the config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GitBackendKind {
+    #[default]
+    Native, // libgit2, no process is started per query
+    Shell, // runs git and parses its output
+}
+
+impl GitBackendKind {
+    pub fn backend(self) -> Arc<dyn GitBackend> {
+        match self {
+            GitBackendKind::Native => Arc::new(NativeGit),
+            GitBackendKind::Shell => Arc::new(ShellGit),
+        }
+    }
+}
+
+/// Repositories that are direct subdirectories of the given directory, sorted by name
+pub fn find_repos(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+    let mut repos: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && path.join(".git").exists())
+        .collect();
+    repos.sort();
+    repos
+}
+
+/// Read the state of all repositories in the directory
+pub async fn scan_repos(dir: &Path, git: Arc<dyn GitBackend>) -> Vec<RepoStatus> {
+    let semaphore = Arc::new(Semaphore::new(MAX_PARALLEL_SCANS));
+    let mut scans = JoinSet::new();
+    for (i, path) in find_repos(dir).into_iter().enumerate() {
+        let semaphore = Arc::clone(&semaphore);
+        let git = Arc::clone(&git);
+        scans.spawn(async move {
+            let _permit = semaphore.acquire().await;
+            (i, read_status(git, path).await)
+        });
+    }
+    let mut repos: Vec<(usize, RepoStatus)> = scans.join_all().await;
+    repos.sort_by_key(|(i, _)| *i);
+    repos.into_iter().map(|(_, repo)| repo).collect()
+}
+
+/// Scan the directory in the background and send the result
+pub fn spawn_scan(dir: PathBuf, git: Arc<dyn GitBackend>, tx: Sender<Vec<RepoStatus>>) {
+    tokio::spawn(async move {
+        let repos = scan_repos(&dir, git).await;
+        let _ = tx.send(repos).await;
+    });
+}
+
+/// Read the status of the repository on a thread that may block
+pub async fn read_status(git: Arc<dyn GitBackend>, path: PathBuf) -> RepoStatus {
+    let mut failed = RepoStatus::new(path.clone());
+    match tokio::task::spawn_blocking(move || git.repo_status(path)).await {
+        Ok(status) => status,
+        Err(err) => {
+            failed.error = Some(err.to_string());
+            failed
+        }
+    }
+}
+
+/// Queries that run git and parse its porcelain output
+pub struct ShellGit;
+
+impl GitBackend for ShellGit {
+    fn repo_status(&self, path: PathBuf) -> RepoStatus {
+        let mut status = RepoStatus::new(path);
+        match git_sync(&status.path, &["status", "--porcelain=v2", "--branch"]) {
+            Ok(output) => parse_status(&output, &mut status),
+            Err(err) => {
+                status.error = Some(err);
+                return status;
+            }
+        }
+        // fails for a repository without commits
+        status.last_commit = git_sync(&status.path, &["log", "-1", "--format=%h %s (%cr)"])
+            .ok()
+            .map(|output| output.trim().to_string())
+            .filter(|commit| !commit.is_empty());
+        status
+    }
+
+    fn changed_files(&self, repo: &Path) -> Result<Vec<FileChange>, String> {
+        // with -z the paths are not quoted and the entries are separated by NUL
+        let output = git_sync(
+            repo,
+            &["status", "--porcelain=v2", "--untracked-files=all", "-z"],
+        )?;
+        let mut files = Vec::new();
+        let mut entries = output.split('\0');
+        while let Some(entry) = entries.next() {
+            let Some((kind, rest)) = entry.split_once(' ') else {
+                continue;
+            };
+            let mut old_path = None;
+            let path = match kind {
+                // 1 <XY> <sub> <mH> <mI> <mW> <hH> <hI> <path>
+                "1" => rest.splitn(8, ' ').nth(7),
+                // u <XY> <sub> <m1> <m2> <m3> <mW> <h1> <h2> <h3> <path>
+                "u" => rest.splitn(10, ' ').nth(9),
+                // 2 <XY> <sub> <mH> <mI> <mW> <hH> <hI> <X><score> <path>, followed by the original path
+                "2" => {
+                    old_path = entries.next();
+                    rest.splitn(9, ' ').nth(8)
+                }
+                "?" => {
+                    files.push(FileChange {
+                        path: rest.to_string(),
+                        old_path: None,
+                        staged: false,
+                        unstaged: true,
+                        untracked: true,
+                    });
+                    continue;
+                }
+                _ => None,
+            };
+            let Some(path) = path else {
+                continue;
+            };
+            let mut xy = rest.chars();
+            files.push(FileChange {
+                path: path.to_string(),
+                old_path: old_path.map(str::to_string),
+                staged: xy.next().is_some_and(|x| x != '.'),
+                unstaged: xy.next().is_some_and(|y| y != '.'),
+                untracked: false,
+            });
+        }
+        Ok(files)
+    }
+
+    fn diff(&self, repo: &Path, file: &FileChange) -> Result<String, String> {
+        if file.untracked {
+            // exits with 1 because the files differ
+            return git_output(repo, &["diff", "--no-index", "--", "/dev/null", &file.path])
+                .map(|(_, stdout, _)| stdout);
+        }
+        let mut diff = String::new();
+        if file.staged {
+            diff.push_str(&git_sync(repo, &["diff", "--cached", "--", &file.path])?);
+        }
+        if file.unstaged {
+            diff.push_str(&git_sync(repo, &["diff", "--", &file.path])?);
+        }
+        Ok(diff)
+    }
+}
+
+/// Parse the output of `git status --porcelain=v2 --branch`
+fn parse_status(output: &str, status: &mut RepoStatus) {
+    for line in output.lines() {
+        if let Some(head) = line.strip_prefix("# branch.head ") {
+            status.branch = (head != "(detached)").then(|| head.to_string());
+        } else if let Some(ab) = line.strip_prefix("# branch.ab ") {
+            // format: +<ahead> -<behind>
+            let mut counts = ab
+                .split_whitespace()
+                .map(|count| count[1..].parse::<usize>().unwrap_or(0));
+            status.ahead_behind = Some((counts.next().unwrap_or(0), counts.next().unwrap_or(0)));
+        } else if line.starts_with("1 ") || line.starts_with("2 ") || line.starts_with("u ") {
+            status.changed += 1;
+        } else if line.starts_with("? ") {
+            status.untracked += 1;
+        }
+    }
+}
+
+/// Run a git command in the repository and return its stdout
+pub async fn git(repo: &Path, args: &[&str]) -> Result<String, String> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        // the command runs in the background, it must neither lock the index nor ask for passwords
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        // messages like "Already up to date" are checked
+        .env("LC_ALL", "C")
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+    // without a controlling terminal ssh can not ask for a passphrase on top of the ui
+    // SAFETY: the closure runs between fork and exec, setsid is async-signal-safe and the errno
+    // is converted without allocating
+    unsafe {
+        command.pre_exec(|| {
+            setsid()?;
+            Ok(())
+        });
+    }
+    let output = command
+        .output()
+        .await
+        .map_err(|err| format!("failed to run git: {}", err))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Add all changes of the file to the index. The removal of the original path of a renamed file
+/// is staged as well, git add would fail for a path that is neither in the index nor on disk.
+pub fn stage(repo: &Path, file: &FileChange) -> Result<(), String> {
+    if let Some(old_path) = &file.old_path {
+        git_sync(
+            repo,
+            &["rm", "-q", "--cached", "--ignore-unmatch", "--", old_path],
+        )?;
+    }
+    git_sync(repo, &["add", "--", &file.path]).map(|_| ())
+}
+
+/// Remove the staged changes of the file from the index, the working tree is not changed. A
+/// renamed file is unstaged together with its original path, otherwise the removal of the
+/// original path would stay staged.
+pub fn unstage(repo: &Path, file: &FileChange) -> Result<(), String> {
+    let mut args = vec!["reset", "-q", "--", file.path.as_str()];
+    args.extend(file.old_path.as_deref());
+    git_sync(repo, &args).map(|_| ())
+}
+
+/// Run a git command in the repository and wait for it. Returns stdout if it succeeded.
+fn git_sync(repo: &Path, args: &[&str]) -> Result<String, String> {
+    let (success, stdout, stderr) = git_output(repo, args)?;
+    if success {
+        Ok(stdout)
+    } else {
+        Err(stderr.trim().to_string())
+    }
+}
+
+/// Run a git command in the repository and return whether it succeeded, stdout and stderr
+fn git_output(repo: &Path, args: &[&str]) -> Result<(bool, String, String), String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        // queries run during the background scan, they must not lock the index
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        // the age of the last commit (%cr) is shown like the one of the native backend
+        .env("LC_ALL", "C")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|err| format!("failed to run git: {}", err))?;
+    Ok((
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::{init_repo, run_git, write, TempDir};
+
+    /// Clone of an upstream repository that is one commit ahead and one behind, with staged,
+    /// unstaged, untracked and renamed files
+    fn repository(temp: &TempDir) -> PathBuf {
+        let upstream = temp.0.join("upstream");
+        init_repo(
+            &upstream,
+            &[
+                ("staged.txt", "one\ntwo\n"),
+                ("unstaged.txt", "one\ntwo\n"),
+                ("both.txt", "one\ntwo\n"),
+                ("renamed.txt", "moved\n"),
+            ],
+        );
+
+        let repo = temp.0.join("repo");
+        run_git(&temp.0, &["clone", "-q", "upstream", "repo"]);
+        write(&upstream, "remote.txt", "remote\n");
+        run_git(&upstream, &["add", "."]);
+        run_git(&upstream, &["commit", "-q", "-m", "remote change"]);
+        write(&repo, "local.txt", "local\n");
+        run_git(&repo, &["add", "."]);
+        run_git(&repo, &["commit", "-q", "-m", "local change"]);
+        run_git(&repo, &["fetch", "-q"]);
+
+        write(&repo, "staged.txt", "one\nstaged\n");
+        write(&repo, "both.txt", "one\nstaged\n");
+        run_git(&repo, &["add", "staged.txt", "both.txt"]);
+        write(&repo, "both.txt", "one\nstaged\nunstaged\n");
+        write(&repo, "unstaged.txt", "one\nunstaged\n");
+        run_git(&repo, &["mv", "renamed.txt", "new name.txt"]);
+        write(&repo, "untracked.txt", "new\n");
+        write(&repo, "dir/nested.txt", "nested\n");
+        repo
+    }
+
+    fn changes(files: &[FileChange]) -> Vec<(String, bool, bool, bool)> {
+        let mut changes: Vec<_> = files
+            .iter()
+            .map(|file| {
+                (
+                    file.path.clone(),
+                    file.staged,
+                    file.unstaged,
+                    file.untracked,
+                )
+            })
+            .collect();
+        changes.sort();
+        changes
+    }
+
+    /// git ends the ---/+++ lines of a path with spaces with a tab, libgit2 does not
+    fn normalize(diff: String) -> String {
+        diff.lines()
+            .map(|line| {
+                if line.starts_with("--- ") || line.starts_with("+++ ") {
+                    line.trim_end_matches('\t')
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn backends_read_the_same_status() {
+        let temp = TempDir::new("status");
+        let repo = repository(&temp);
+        let shell = ShellGit.repo_status(repo.clone());
+        let native = NativeGit.repo_status(repo);
+        assert_eq!(shell.error, None);
+        assert_eq!(native.error, None);
+        assert_eq!(shell.branch.as_deref(), Some("main"));
+        assert_eq!(shell.ahead_behind, Some((1, 1)));
+        assert_eq!(shell.changed, 4);
+        assert_eq!(shell.untracked, 2);
+        assert!(shell
+            .last_commit
+            .as_deref()
+            .unwrap()
+            .contains("local change"));
+        assert_eq!(shell.branch, native.branch);
+        assert_eq!(shell.ahead_behind, native.ahead_behind);
+        assert_eq!(shell.changed, native.changed);
+        assert_eq!(shell.untracked, native.untracked);
+        assert_eq!(shell.last_commit, native.last_commit);
+    }
+
+    #[test]
+    fn backends_list_the_same_files_and_diffs() {
+        let temp = TempDir::new("files");
+        let repo = repository(&temp);
+        let shell = ShellGit.changed_files(&repo).unwrap();
+        let native = NativeGit.changed_files(&repo).unwrap();
+        assert_eq!(changes(&shell), changes(&native));
+        assert_eq!(
+            changes(&shell),
+            [
+                ("both.txt".to_string(), true, true, false),
+                ("dir/nested.txt".to_string(), false, true, true),
+                ("new name.txt".to_string(), true, false, false),
+                ("staged.txt".to_string(), true, false, false),
+                ("unstaged.txt".to_string(), false, true, false),
+                ("untracked.txt".to_string(), false, true, true),
+            ]
+        );
+        for files in [&shell, &native] {
+            let renamed = files
+                .iter()
+                .find(|file| file.path == "new name.txt")
+                .unwrap();
+            assert_eq!(renamed.old_path.as_deref(), Some("renamed.txt"));
+        }
+        let both = shell.iter().find(|file| file.path == "both.txt").unwrap();
+        let both = NativeGit.diff(&repo, both).unwrap();
+        assert!(both.contains("+staged\n") && both.contains("+unstaged\n"));
+        for file in &shell {
+            assert_eq!(
+                normalize(ShellGit.diff(&repo, file).unwrap()),
+                normalize(NativeGit.diff(&repo, file).unwrap()),
+                "diff of {}",
+                file.path
+            );
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use git2::{
+    Branch, DiffFormat, DiffOptions, ErrorCode, Repository, Status, StatusOptions, StatusShow,
+};
+
+use crate::git::{FileChange, GitBackend, RepoStatus};
+
+// changes in the index compared to HEAD
+const INDEX_CHANGES: Status = Status::INDEX_NEW
+    .union(Status::INDEX_MODIFIED)
+    .union(Status::INDEX_DELETED)
+    .union(Status::INDEX_RENAMED)
+    .union(Status::INDEX_TYPECHANGE);
+// changes in the working tree compared to the index, without untracked files
+const WORKTREE_CHANGES: Status = Status::WT_MODIFIED
+    .union(Status::WT_DELETED)
+    .union(Status::WT_RENAMED)
+    .union(Status::WT_TYPECHANGE);
+
+/// Queries that use libgit2. The results do not depend on the git version or the locale.
+pub struct NativeGit;
+
+impl GitBackend for NativeGit {
+    fn repo_status(&self, path: PathBuf) -> RepoStatus {
+        let mut status = RepoStatus::new(path);
+        if let Err(err) = read_status(&mut status) {
+            status.error = Some(err.message().to_string());
+        }
+        status
+    }
+
+    fn changed_files(&self, repo: &Path) -> Result<Vec<FileChange>, String> {
+        let repo = open(repo)?;
+        let mut options = StatusOptions::new();
+        options
+            .show(StatusShow::IndexAndWorkdir)
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .renames_head_to_index(true);
+        let statuses = repo
+            .statuses(Some(&mut options))
+            .map_err(|err| err.message().to_string())?;
+        let files = statuses
+            .iter()
+            .filter_map(|entry| {
+                let status = entry.status();
+                // a renamed file is listed with its new path
+                let path = entry
+                    .head_to_index()
+                    .and_then(|delta| delta.new_file().path())
+                    .and_then(Path::to_str)
+                    .or(entry.path())?
+                    .to_string();
+                let old_path = entry
+                    .head_to_index()
+                    .filter(|_| status.contains(Status::INDEX_RENAMED))
+                    .and_then(|delta| delta.old_file().path())
+                    .and_then(Path::to_str)
+                    .map(str::to_string);
+                let conflicted = status.contains(Status::CONFLICTED);
+                Some(FileChange {
+                    path,
+                    old_path,
+                    staged: conflicted || status.intersects(INDEX_CHANGES),
+                    unstaged: conflicted
+                        || status.intersects(WORKTREE_CHANGES.union(Status::WT_NEW)),
+                    untracked: status.contains(Status::WT_NEW),
+                })
+            })
+            .collect();
+        Ok(files)
+    }
+
+    fn diff(&self, repo: &Path, file: &FileChange) -> Result<String, String> {
+        let repo = open(repo)?;
+        let message = |err: git2::Error| err.message().to_string();
+        let options = || {
+            let mut options = DiffOptions::new();
+            options
+                .pathspec(&file.path)
+                .disable_pathspec_match(true)
+                .include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            options
+        };
+        let mut diffs = Vec::new();
+        if file.staged {
+            // a repository without commits has no HEAD to compare with
+            let head = match repo.head() {
+                Ok(head) => Some(head.peel_to_tree().map_err(message)?),
+                Err(err) if err.code() == ErrorCode::UnbornBranch => None,
+                Err(err) => return Err(message(err)),
+            };
+            diffs.push(
+                repo.diff_tree_to_index(head.as_ref(), None, Some(&mut options()))
+                    .map_err(message)?,
+            );
+        }
+        if file.unstaged {
+            diffs.push(
+                repo.diff_index_to_workdir(None, Some(&mut options()))
+                    .map_err(message)?,
+            );
+        }
+        let mut patch = String::new();
+        for diff in diffs {
+            diff.print(DiffFormat::Patch, |_, _, line| {
+                // content lines are printed without their +, - or space prefix
+                if matches!(line.origin(), '+' | '-' | ' ') {
+                    patch.push(line.origin());
+                }
+                patch.push_str(&String::from_utf8_lossy(line.content()));
+                true
+            })
+            .map_err(message)?;
+        }
+        Ok(patch)
+    }
+}
+
+fn open(repo: &Path) -> Result<Repository, String> {
+    Repository::open(repo).map_err(|err| err.message().to_string())
+}
+
+/// Fill in the status of the repository
+fn read_status(status: &mut RepoStatus) -> Result<(), git2::Error> {
+    let repo = Repository::open(&status.path)?;
+    match repo.head() {
+        Ok(head) => {
+            let commit = head.peel_to_commit()?;
+            let short_id = commit.as_object().short_id()?;
+            status.last_commit = Some(format!(
+                "{} {} ({})",
+                short_id.as_str().unwrap_or_default(),
+                commit.summary().unwrap_or_default(),
+                relative_time(commit.time().seconds())
+            ));
+            if head.is_branch() {
+                status.branch = head.shorthand().map(String::from);
+                // the upstream branch may be configured but not fetched yet
+                if let Ok(upstream) = Branch::wrap(head).upstream() {
+                    if let Some(upstream) = upstream.get().target() {
+                        status.ahead_behind = Some(repo.graph_ahead_behind(commit.id(), upstream)?);
+                    }
+                }
+            }
+        }
+        // HEAD points to the branch of a repository without commits
+        Err(err) if err.code() == ErrorCode::UnbornBranch => {
+            let head = repo.find_reference("HEAD")?;
+            status.branch = head
+                .symbolic_target()
+                .map(|target| target.trim_start_matches("refs/heads/").to_string());
+        }
+        Err(err) => return Err(err),
+    }
+
+    // untracked directories count as one file like in `git status`
+    let mut options = StatusOptions::new();
+    options
+        .show(StatusShow::IndexAndWorkdir)
+        .include_untracked(true)
+        .recurse_untracked_dirs(false)
+        .renames_head_to_index(true);
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let flags = entry.status();
+        if flags.contains(Status::WT_NEW) {
+            status.untracked += 1;
+        } else if flags.intersects(
+            INDEX_CHANGES
+                .union(WORKTREE_CHANGES)
+                .union(Status::CONFLICTED),
+        ) {
+            status.changed += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Age of a commit in the format of `git log --format=%cr`, e.g. "3 days ago"
+fn relative_time(timestamp: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let seconds = now - timestamp;
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+    let count = |count: i64, unit: &str| {
+        let plural = if count == 1 { "" } else { "s" };
+        format!("{} {}{}", count, unit, plural)
+    };
+    let ago = |amount: i64, unit: &str| format!("{} ago", count(amount, unit));
+    // the same thresholds and rounding as git
+    if seconds < 90 {
+        return ago(seconds, "second");
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return ago(minutes, "minute");
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return ago(hours, "hour");
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return ago(days, "day");
+    }
+    if days < 70 {
+        return ago((days + 3) / 7, "week");
+    }
+    if days < 365 {
+        return ago((days + 15) / 30, "month");
+    }
+    if days < 1825 {
+        let months = (days * 12 * 2 + 365) / (365 * 2);
+        return match months % 12 {
+            0 => ago(months / 12, "year"),
+            rest => format!(
+                "{}, {} ago",
+                count(months / 12, "year"),
+                count(rest, "month")
+            ),
+        };
+    }
+    ago((days + 183) / 365, "year")
+}
@@ -0,0 +1,723 @@
+use std::{
+    io::{IsTerminal, Write},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::bail;
+use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, Termios};
+use serde::Serialize;
+use tokio::{io::AsyncReadExt, sync::mpsc};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    app::Config,
+    cli::Subcommand,
+    functions::{signal_name, InputMessage, OutputMessage, Stream, TaskHandle, TaskSummary},
+    privilege::root_access,
+    secret::Secret,
+    tasks::{ParamKind, ParamValues, Task, TaskCatalog},
+};
+
+// exit code of a task whose step timed out, the same as of timeout(1)
+const TIMEOUT_EXIT_CODE: i32 = 124;
+// exit code of a task that was cancelled with Ctrl+C, like a shell that got SIGINT
+const CANCELLED_EXIT_CODE: i32 = 130;
+
+/// Run the command without the user interface and return the exit code of the process
+pub async fn run(command: Subcommand, config: Config, catalog: TaskCatalog) -> i32 {
+    let tasks = &catalog.tasks;
+    let json = matches!(command, Subcommand::Run { json: true, .. });
+    let result = match command {
+        Subcommand::List => {
+            list(tasks);
+            Ok(0)
+        }
+        Subcommand::Describe { task } => find_task(tasks, &task).map(|task| {
+            describe(task);
+            0
+        }),
+        Subcommand::Run { task, params, json } => match find_task(tasks, &task) {
+            Ok(task) => run_task(task, params, json, &config).await,
+            Err(err) => Err(err),
+        },
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("Error: {:#}", err);
+        if json {
+            emit(&Event::Error {
+                message: format!("{:#}", err),
+            });
+        }
+        1
+    })
+}
+
+/// Name of the task including its category, e.g. system/services/Failed services
+fn qualified_name(task: &Task) -> String {
+    if task.category.is_empty() {
+        task.name.clone()
+    } else {
+        format!("{}/{}", task.category, task.name)
+    }
+}
+
+/// Find a task by its name or by category/name
+fn find_task<'a>(tasks: &'a [Task], name: &str) -> anyhow::Result<&'a Task> {
+    let matches: Vec<&Task> = tasks
+        .iter()
+        .filter(|task| task.name == name || qualified_name(task) == name)
+        .collect();
+    match matches.as_slice() {
+        [task] => Ok(task),
+        [] => bail!("unknown task '{}', see `linutil_rs list`", name),
+        _ => bail!(
+            "there are several tasks named '{}', use category/name",
+            name
+        ),
+    }
+}
+
+/// Print one line per task with its name and description
+fn list(tasks: &[Task]) {
+    let names: Vec<String> = tasks.iter().map(qualified_name).collect();
+    let width = names.iter().map(|name| name.chars().count()).max();
+    for (name, task) in names.iter().zip(tasks) {
+        if task.description.is_empty() {
+            println!("{}", name);
+        } else {
+            println!(
+                "{:width$}  {}",
+                name,
+                task.description,
+                width = width.unwrap_or(0)
+            );
+        }
+    }
+}
+
+/// Print the settings, parameters and steps of the task
+fn describe(task: &Task) {
+    println!("{}", qualified_name(task));
+    if !task.description.is_empty() {
+        println!("  {}", task.description);
+    }
+    if task.workflow.is_some() {
+        println!("  opens a screen of the user interface, it can not be run from the command line");
+    }
+    if let Some(dir) = &task.working_dir {
+        println!("  working directory: {}", dir);
+    }
+    if task.pty {
+        println!("  runs in a pseudo-terminal");
+    }
+    if task.requires_root {
+        match root_access() {
+            Ok(Some(tool)) => println!("  requires root, runs with {}", tool.program()),
+            Ok(None) => println!("  requires root"),
+            Err(err) => println!("  {}", err),
+        }
+    }
+    if let Some(timeout) = task.timeout {
+        println!("  timeout: {}s per step", timeout);
+    }
+    if task.retry.max_attempts > 1 {
+        println!(
+            "  retry: {} attempts, {}s backoff, factor {}",
+            task.retry.max_attempts, task.retry.backoff, task.retry.backoff_factor
+        );
+    }
+
+    if !task.params.is_empty() {
+        println!("\nParameters (--param <name>=<value>):");
+        for param in &task.params {
+            let mut details = vec![kind_name(&param.kind)];
+            if param.optional {
+                details.push("optional".to_string());
+            }
+            let default = param.default_value();
+            if !default.is_empty() {
+                details.push(format!("default {}", default));
+            }
+            print!("  {} ({})", param.name, details.join(", "));
+            match &param.label {
+                Some(label) => println!("  {}", label),
+                None => println!(),
+            }
+        }
+    }
+
+    if !task.steps.is_empty() {
+        println!("\nSteps:");
+        for (i, step) in task.steps.iter().enumerate() {
+            match &step.name {
+                Some(name) => println!("  {}. {}: {}", i + 1, name, step.run),
+                None => println!("  {}. {}", i + 1, step.run),
+            }
+        }
+    }
+}
+
+/// Type of a parameter as it is shown by describe, e.g. "integer 1..10"
+fn kind_name(kind: &ParamKind) -> String {
+    match kind {
+        ParamKind::String => "string".to_string(),
+        ParamKind::Password => "password".to_string(),
+        ParamKind::Boolean => "true/false".to_string(),
+        ParamKind::Choice { choices } => format!("one of {}", choices.join(", ")),
+        ParamKind::Path { must_exist: true } => "existing path".to_string(),
+        ParamKind::Path { must_exist: false } => "path".to_string(),
+        ParamKind::Integer { min, max } => match (min, max) {
+            (Some(min), Some(max)) => format!("integer {}..{}", min, max),
+            (Some(min), None) => format!("integer >= {}", min),
+            (None, Some(max)) => format!("integer <= {}", max),
+            (None, None) => "integer".to_string(),
+        },
+    }
+}
+
+/// Values of the parameters of the task. Parameters that are not given use their default, every
+/// value is validated like in the form.
+fn param_values(task: &Task, given: ParamValues) -> anyhow::Result<ParamValues> {
+    if let Some((name, _)) = given
+        .iter()
+        .find(|(name, _)| !task.params.iter().any(|param| &param.name == name))
+    {
+        bail!("{} has no parameter '{}'", task.name, name);
+    }
+    let mut values = Vec::new();
+    let mut errors = Vec::new();
+    for param in &task.params {
+        let value = given
+            .iter()
+            .rev()
+            .find(|(name, _)| name == &param.name)
+            .map_or_else(
+                || Zeroizing::new(param.default_value()),
+                |(_, value)| value.clone(),
+            );
+        match param.validate(&value) {
+            Ok(value) => values.push((param.name.clone(), Zeroizing::new(value))),
+            Err(err) => errors.push(format!("--param {}: {}", param.name, err)),
+        }
+    }
+    if !errors.is_empty() {
+        bail!(
+            "invalid parameters for {}\n  {}",
+            task.name,
+            errors.join("\n  ")
+        );
+    }
+    Ok(values)
+}
+
+/// Run the steps of the task and print their output, or with `json` the events of the run. Ctrl+C
+/// cancels the task like in the user interface. Returns the exit code of the failed step or 0 if
+/// all steps succeeded.
+async fn run_task(
+    task: &Task,
+    params: ParamValues,
+    json: bool,
+    config: &Config,
+) -> anyhow::Result<i32> {
+    if task.workflow.is_some() {
+        bail!(
+            "{} opens a screen of the user interface and can not be run from the command line",
+            task.name
+        );
+    }
+    let mut values = param_values(task, params)?;
+    values.extend(config.params());
+    let steps = task.steps_with_params(&values);
+    let mut options = task.run_options(&values);
+    if task.requires_root {
+        options.root = match root_access() {
+            Ok(tool) => tool,
+            Err(err) => bail!("{} {}", task.name, err),
+        };
+        // the steps run in a process group of their own, which is stopped when it reads from the
+        // terminal. sudo reads the password from the forwarded stdin instead.
+        options.ask_password = true;
+    }
+    let names: Vec<String> = steps
+        .iter()
+        .map(|step| step.display_name().to_string())
+        .collect();
+
+    let (tx_output, mut rx_output) = mpsc::channel::<OutputMessage>(100);
+    let (tx_input, rx_input) = mpsc::channel::<InputMessage>(5);
+    let echo = Echo::default();
+    forward_stdin(tx_input, echo.clone());
+    let handle = TaskHandle::spawn(tx_output, rx_input, steps, options);
+
+    if json {
+        emit(&Event::TaskStarted {
+            task: qualified_name(task),
+            steps: names.len(),
+        });
+    }
+    let mut printer = Printer::default();
+    let mut summary = None;
+    loop {
+        tokio::select! {
+            msg = rx_output.recv() => {
+                // the task stopped, with an error if it did not send a summary
+                let Some(msg) = msg else {
+                    break;
+                };
+                if let OutputMessage::PasswordRequested { .. } = msg {
+                    echo.hide();
+                }
+                if json {
+                    if let Some(event) = Event::from_message(&msg, &names) {
+                        emit(&event);
+                    }
+                } else {
+                    printer.show(&msg);
+                }
+                if let OutputMessage::Finished(finished) = msg {
+                    summary = Some(finished);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => handle.cancel(),
+        }
+    }
+    // the task may have been cancelled while sudo waited for the password
+    echo.show();
+
+    let Some(summary) = summary else {
+        if json {
+            emit(&Event::Error {
+                message: "the task stopped without a result".to_string(),
+            });
+        }
+        return Ok(1);
+    };
+    if json {
+        return Ok(exit_code(&summary));
+    }
+    if let Some((_, step)) = summary.failed_step() {
+        if let Some(completion) = &step.completion {
+            printer.print(
+                Stream::Info,
+                &format!("{} failed: {}", step.name, completion.reason()),
+                true,
+            );
+        }
+    } else if summary.cancelled {
+        printer.print(Stream::Info, "cancelled", true);
+    }
+    Ok(exit_code(&summary))
+}
+
+/// Exit code of the process for the result of the task. Steps that were terminated by a signal
+/// exit with 128 + signal like in a shell. A task that was cancelled in a step that is allowed to
+/// fail exits with 130.
+fn exit_code(summary: &TaskSummary) -> i32 {
+    let Some(completion) = summary
+        .failed_step()
+        .and_then(|(_, step)| step.completion.as_ref())
+    else {
+        return if summary.cancelled {
+            CANCELLED_EXIT_CODE
+        } else {
+            0
+        };
+    };
+    if completion.timed_out {
+        return TIMEOUT_EXIT_CODE;
+    }
+    match (
+        completion.code,
+        completion.signal.or(completion.stopped_with),
+    ) {
+        (Some(code), _) if code != 0 => code,
+        (_, Some(signal)) => 128 + signal,
+        _ => 1,
+    }
+}
+
+/// Send stdin to the task as it is read. At the end of stdin the channel is closed, which closes
+/// stdin of the running and all following commands. The echo that was turned off for a password is
+/// turned on again as soon as the password was read.
+fn forward_stdin(tx: mpsc::Sender<InputMessage>, echo: Echo) {
+    tokio::spawn(async move {
+        let mut stdin = tokio::io::stdin();
+        // stdin may contain the password for sudo, so the buffer and every chunk are wiped
+        let mut buffer = Zeroizing::new([0; 4096]);
+        while let Ok(count @ 1..) = stdin.read(&mut buffer[..]).await {
+            // the line with the password was read
+            echo.show();
+            let data = InputMessage::Secret(Secret::new(buffer[..count].to_vec()));
+            buffer[..count].zeroize();
+            if tx.send(data).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Echo of the terminal that stdin is read from. It is turned off while sudo waits for a password,
+/// until the line with the password was read.
+#[derive(Clone, Default)]
+struct Echo {
+    saved: Arc<Mutex<Option<Termios>>>, // settings of the terminal while the echo is off
+}
+
+impl Echo {
+    /// Turn the echo off, nothing happens if stdin is not a terminal
+    fn hide(&self) {
+        let stdin = std::io::stdin();
+        let mut saved = self.saved.lock().unwrap();
+        if saved.is_some() || !stdin.is_terminal() {
+            return;
+        }
+        let Ok(settings) = tcgetattr(&stdin) else {
+            return;
+        };
+        let mut hidden = settings.clone();
+        hidden.local_flags.remove(LocalFlags::ECHO);
+        if tcsetattr(&stdin, SetArg::TCSANOW, &hidden).is_ok() {
+            *saved = Some(settings);
+        }
+    }
+
+    /// Turn the echo on again. Returns if it was off.
+    fn show(&self) -> bool {
+        let Some(settings) = self.saved.lock().unwrap().take() else {
+            return false;
+        };
+        let _ = tcsetattr(std::io::stdin(), SetArg::TCSANOW, &settings);
+        // the newline of the hidden input was not echoed either
+        eprintln!();
+        true
+    }
+}
+
+/// Writes the output of the task to stdout and the messages of linutil and stderr of the task to
+/// stderr. Unfinished lines of a pseudo-terminal are printed as they arrive, only the rest is
+/// printed when the line is complete.
+#[derive(Default)]
+struct Printer {
+    partial: String, // unfinished line that is printed already
+}
+
+impl Printer {
+    fn show(&mut self, msg: &OutputMessage) {
+        match msg {
+            OutputMessage::Line(stream, line) => self.print(*stream, line, true),
+            OutputMessage::Partial(stream, text) => self.print(*stream, text, false),
+            OutputMessage::StepStarted { index, total, name } if *total > 1 => self.print(
+                Stream::Info,
+                &format!("[{}/{}] {}", index + 1, total, name),
+                true,
+            ),
+            OutputMessage::PasswordRequested { retry } => {
+                let message = if *retry {
+                    "Wrong password, enter the password for sudo again: "
+                } else {
+                    "Password for sudo: "
+                };
+                eprint!("{}", message);
+            }
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, stream: Stream, text: &str, complete: bool) {
+        if stream != Stream::Stdout {
+            eprintln!("{}", text);
+            return;
+        }
+        let mut stdout = std::io::stdout().lock();
+        let _ = match text.strip_prefix(self.partial.as_str()) {
+            Some(rest) => write!(stdout, "{}", rest),
+            // the line was rewritten, e.g. by a progress bar
+            None => write!(stdout, "\r{}", text),
+        };
+        if complete {
+            let _ = writeln!(stdout);
+            self.partial.clear();
+        } else {
+            self.partial = text.to_string();
+        }
+        let _ = stdout.flush();
+    }
+}
+
+/// Event of a run that is printed as one line of JSON with `--json`, e.g.
+/// `{"event":"output","stream":"stdout","line":"..."}`
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    TaskStarted {
+        task: String,
+        steps: usize,
+    },
+    StepStarted {
+        index: usize,
+        total: usize,
+        name: &'a str,
+    },
+    // a complete line, unfinished lines of a pseudo-terminal are not reported
+    Output {
+        stream: Stream,
+        line: &'a str,
+    },
+    StepFinished {
+        index: usize,
+        name: &'a str,
+        success: bool,
+        exit_code: Option<i32>,
+        signal: Option<String>,       // signal that terminated the step
+        stopped_with: Option<String>, // signal that was sent because of Ctrl+C or the timeout
+        timed_out: bool,
+        attempt: u32,
+        duration_ms: u128,
+        reason: String,
+    },
+    // sudo waits for the password on stdin, retry is set if the last one was wrong
+    PasswordRequested {
+        retry: bool,
+    },
+    TaskFinished {
+        success: bool,
+        exit_code: i32, // exit code of the process
+        succeeded: usize,
+        failed_step: Option<&'a str>,
+        cancelled: bool,
+        duration_ms: u128,
+    },
+    // the task could not be run
+    Error {
+        message: String,
+    },
+}
+
+impl<'a> Event<'a> {
+    /// Event for a message of the running task, `names` are the names of the steps
+    fn from_message(msg: &'a OutputMessage, names: &'a [String]) -> Option<Event<'a>> {
+        let event = match msg {
+            OutputMessage::Line(stream, line) => Event::Output {
+                stream: *stream,
+                line,
+            },
+            OutputMessage::Partial(..) => return None,
+            OutputMessage::PasswordRequested { retry } => {
+                Event::PasswordRequested { retry: *retry }
+            }
+            OutputMessage::StepStarted { index, total, name } => Event::StepStarted {
+                index: *index,
+                total: *total,
+                name,
+            },
+            OutputMessage::StepFinished { index, completion } => Event::StepFinished {
+                index: *index,
+                name: names.get(*index).map_or("", String::as_str),
+                success: completion.success(),
+                exit_code: completion.code,
+                signal: completion.signal.map(signal_name),
+                stopped_with: completion.stopped_with.map(signal_name),
+                timed_out: completion.timed_out,
+                attempt: completion.attempt,
+                duration_ms: completion.duration.as_millis(),
+                reason: completion.reason(),
+            },
+            OutputMessage::Finished(summary) => Event::TaskFinished {
+                success: summary.success(),
+                exit_code: exit_code(summary),
+                succeeded: summary.succeeded(),
+                failed_step: summary.failed_step().map(|(_, step)| step.name.as_str()),
+                cancelled: summary.cancelled,
+                duration_ms: summary.duration.as_millis(),
+            },
+        };
+        Some(event)
+    }
+}
+
+/// Print the event as one line of JSON
+fn emit(event: &Event) {
+    let mut stdout = std::io::stdout().lock();
+    if let Ok(line) = serde_json::to_string(event) {
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nix::sys::signal::Signal;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::functions::{Completion, StepResult};
+
+    fn completion(code: Option<i32>, signal: Option<i32>, stopped_with: Option<i32>) -> Completion {
+        Completion {
+            code,
+            signal,
+            stopped_with,
+            timed_out: false,
+            attempt: 1,
+            duration: Duration::from_secs(1),
+        }
+    }
+
+    /// Task with a first step that succeeded and a second step with the completion
+    fn summary(second: Completion, continue_on_error: bool, cancelled: bool) -> TaskSummary {
+        let step = |name: &str, completion, continue_on_error| StepResult {
+            name: name.to_string(),
+            continue_on_error,
+            completion: Some(completion),
+        };
+        TaskSummary {
+            steps: vec![
+                step("build", completion(Some(0), None, None), false),
+                step("install", second, continue_on_error),
+            ],
+            duration: Duration::from_secs(2),
+            cancelled,
+        }
+    }
+
+    #[test]
+    fn exit_code_of_a_successful_task_is_zero() {
+        let done = summary(completion(Some(0), None, None), false, false);
+        assert_eq!(exit_code(&done), 0);
+        let allowed_failure = summary(completion(Some(3), None, None), true, false);
+        assert_eq!(exit_code(&allowed_failure), 0);
+    }
+
+    #[test]
+    fn exit_code_of_a_failed_step_is_passed_on() {
+        let failed = summary(completion(Some(3), None, None), false, false);
+        assert_eq!(exit_code(&failed), 3);
+        let killed = summary(
+            completion(None, Some(Signal::SIGKILL as i32), None),
+            false,
+            false,
+        );
+        assert_eq!(exit_code(&killed), 137);
+    }
+
+    #[test]
+    fn exit_code_of_a_cancelled_task_is_130() {
+        let sigint = Some(Signal::SIGINT as i32);
+        let cancelled = summary(completion(None, sigint, sigint), false, true);
+        assert_eq!(exit_code(&cancelled), 130);
+        // the command handled SIGINT and exited with 0
+        let handled = summary(completion(Some(0), None, sigint), false, true);
+        assert_eq!(exit_code(&handled), 130);
+        let allowed_to_fail = summary(completion(None, sigint, sigint), true, true);
+        assert_eq!(exit_code(&allowed_to_fail), 130);
+    }
+
+    #[test]
+    fn exit_code_of_a_timeout_is_124() {
+        let sigterm = Some(Signal::SIGTERM as i32);
+        let timed_out = Completion {
+            timed_out: true,
+            ..completion(None, sigterm, sigterm)
+        };
+        assert_eq!(exit_code(&summary(timed_out, false, false)), 124);
+    }
+
+    fn to_json(event: &Event) -> Value {
+        serde_json::from_str(&serde_json::to_string(event).unwrap()).unwrap()
+    }
+
+    /// Event of the message as JSON
+    fn message_json(msg: OutputMessage) -> Option<Value> {
+        let names = ["build".to_string(), "install".to_string()];
+        Event::from_message(&msg, &names).map(|event| to_json(&event))
+    }
+
+    #[test]
+    fn events_of_the_task_are_tagged_json() {
+        let started = Event::TaskStarted {
+            task: "System/Update".to_string(),
+            steps: 2,
+        };
+        assert_eq!(
+            to_json(&started),
+            json!({"event": "task_started", "task": "System/Update", "steps": 2})
+        );
+        let error = Event::Error {
+            message: "no task named foo".to_string(),
+        };
+        assert_eq!(
+            to_json(&error),
+            json!({"event": "error", "message": "no task named foo"})
+        );
+    }
+
+    #[test]
+    fn output_events_are_json() {
+        let step = OutputMessage::StepStarted {
+            index: 1,
+            total: 2,
+            name: "install".to_string(),
+        };
+        assert_eq!(
+            message_json(step),
+            Some(json!({"event": "step_started", "index": 1, "total": 2, "name": "install"}))
+        );
+        let line = OutputMessage::Line(Stream::Stderr, "warning: old".to_string());
+        assert_eq!(
+            message_json(line),
+            Some(json!({"event": "output", "stream": "stderr", "line": "warning: old"}))
+        );
+        let password = OutputMessage::PasswordRequested { retry: true };
+        assert_eq!(
+            message_json(password),
+            Some(json!({"event": "password_requested", "retry": true}))
+        );
+        let partial = OutputMessage::Partial(Stream::Stdout, "Password: ".to_string());
+        assert_eq!(message_json(partial), None);
+    }
+
+    #[test]
+    fn finished_events_are_json() {
+        let sigterm = Some(Signal::SIGTERM as i32);
+        let timed_out = Completion {
+            timed_out: true,
+            ..completion(None, sigterm, sigterm)
+        };
+        let step = OutputMessage::StepFinished {
+            index: 1,
+            completion: timed_out,
+        };
+        assert_eq!(
+            message_json(step),
+            Some(json!({
+                "event": "step_finished",
+                "index": 1,
+                "name": "install",
+                "success": false,
+                "exit_code": null,
+                "signal": "SIGTERM",
+                "stopped_with": "SIGTERM",
+                "timed_out": true,
+                "attempt": 1,
+                "duration_ms": 1000,
+                "reason": "timed out, stopped with SIGTERM, terminated by SIGTERM",
+            }))
+        );
+        let failed = summary(completion(Some(3), None, None), false, false);
+        assert_eq!(
+            message_json(OutputMessage::Finished(failed)),
+            Some(json!({
+                "event": "task_finished",
+                "success": false,
+                "exit_code": 3,
+                "succeeded": 1,
+                "failed_step": "install",
+                "cancelled": false,
+                "duration_ms": 2000,
+            }))
+        );
+    }
+}
@@ -1,7 +1,9 @@
 // https://github.com/Thodin/ratatui-background-process-example/blob/master/src/main.rs
 
 use std::{
-    fmt::format, io::{self, stderr, stdout}, time::Duration
+    io::{stderr, stdout},
+    path::PathBuf,
+    time::Duration,
 };
 
 use ratatui::{
@@ -14,24 +16,96 @@ use ratatui::{
     prelude::{Backend, CrosstermBackend},
     Terminal,
 };
-use tokio::sync::{mpsc, Mutex};
-
-// arc
-use std::sync::Arc;
+use tokio::sync::mpsc;
 
 // include other rs-files in source-directory
+mod ansi;
 mod app;
+mod bulk;
+mod cli;
+mod clone;
+mod dashboard;
+mod editor;
+mod forge;
+mod form;
 mod functions;
+mod git;
+mod git_native;
+mod headless;
+mod menu;
+mod output;
+mod privilege;
+mod pty;
+mod review;
+mod run;
+mod secret;
+mod session;
+mod tasks;
+#[cfg(test)]
+mod test_repo;
 mod ui;
+mod validate;
+mod watch;
 use crate::{
-    app::{App, CurrentScreen},
-    functions::run_command,
+    app::{App, Config, CurrentScreen},
+    bulk::{spawn_bulk, BulkOp, Outcome},
+    cli::{parse_args, CliArgs, USAGE},
+    forge::{spawn_list_repos, RepoListing},
+    functions::{config_files, expand_home, read_config, read_tasks, task_file, InputMessage},
+    git::{spawn_scan, RepoStatus},
+    review::{ReviewFocus, ReviewUpdate},
+    session::Session,
+    tasks::TaskCatalog,
+    validate::ConfigErrors,
+    watch::FileWatcher,
 };
 
 /// main function. wrapper for terminal setup, start of app, clearing terminal and handling errors
 /// of the app
 #[tokio::main]
-async fn main() -> Result<(), io::Error> {
+async fn main() -> anyhow::Result<()> {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            std::process::exit(1);
+        }
+    };
+    if args.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    // read the config and the task catalog before the terminal is set up
+    let loaded = load(&args);
+    if args.check_config {
+        match loaded {
+            Ok((config, catalog)) => {
+                for warning in &config.warnings {
+                    eprintln!("warning: {}", warning);
+                }
+                println!(
+                    "Configuration is valid, {} tasks in {}",
+                    catalog.tasks.len(),
+                    task_file(&config).display()
+                );
+                return Ok(());
+            }
+            Err(errors) => exit_with_errors(&errors),
+        }
+    }
+
+    // commands like `run` print to stdout and stderr instead of setting up the terminal
+    if let Some(command) = args.command.clone() {
+        match loaded {
+            Ok((config, catalog)) => {
+                let code = headless::run(command, config, catalog).await;
+                std::process::exit(code);
+            }
+            Err(errors) => exit_with_errors(&errors),
+        }
+    }
+
     // setup terminal
     enable_raw_mode()?;
     let stderr = stderr();
@@ -39,36 +113,203 @@ async fn main() -> Result<(), io::Error> {
     let backend = CrosstermBackend::new(stderr);
     let mut terminal = Terminal::new(backend)?;
 
-    // create and run the app
-    let mut app = App::new();
-    app.items.push("Clone repo".to_string());
-    app.items.push("Push repo".to_string());
-
-    let _result = run_app(&mut terminal, &mut app).await;
+    let result = match loaded {
+        // create and run the app
+        Ok((config, catalog)) => {
+            let mut app = App::new(catalog.tasks, config);
+            run_app(&mut terminal, &mut app, &args).await
+        }
+        // show the problems until the user quits, they are printed again after the cleanup
+        Err(errors) => {
+            let result = show_config_errors(&mut terminal, &errors);
+            ratatui::restore();
+            result?;
+            exit_with_errors(&errors);
+        }
+    };
 
     // cleanup terminal
     ratatui::restore();
 
-    Ok(())
+    result
+}
+
+/// Print the problems of the configuration and exit with a non-zero exit code
+fn exit_with_errors(errors: &ConfigErrors) -> ! {
+    eprintln!("{}", errors);
+    std::process::exit(1);
+}
+
+/// Show the problems of the configuration until the user closes the app
+fn show_config_errors<B: Backend>(
+    terminal: &mut Terminal<B>,
+    errors: &ConfigErrors,
+) -> anyhow::Result<()> {
+    loop {
+        terminal.draw(|frame| ui::config_errors(frame, errors))?;
+        if let Event::Key(key) = event::read()? {
+            let quit = match key.code {
+                KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => true,
+                KeyCode::Char('c') => key.modifiers == KeyModifiers::CONTROL,
+                _ => false,
+            };
+            if quit && key.kind != event::KeyEventKind::Release {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Read the config and the task catalog of the configured task file
+fn load(args: &CliArgs) -> Result<(Config, TaskCatalog), ConfigErrors> {
+    let config = read_config(args)?;
+    let catalog = read_tasks(&task_file(&config))?;
+    Ok((config, catalog))
+}
+
+/// Config files and task catalog whose changes are applied while the app is running
+fn watched_files(args: &CliArgs, config: &Config) -> Vec<PathBuf> {
+    let mut files = config_files(args);
+    files.push(task_file(config));
+    files
+}
+
+/// Read the config and the task catalog again. If one of them is invalid, the previous ones are
+/// kept and the problems are shown in a toast.
+fn reload(app: &mut App, args: &CliArgs) {
+    let loaded = load(args);
+    match loaded {
+        Ok((config, catalog)) => {
+            let message = match config.warnings_text() {
+                Some(warnings) => format!("Configuration reloaded:\n{}", warnings),
+                None => "Configuration reloaded".to_string(),
+            };
+            app.reload(catalog.tasks, config);
+            app.show_toast(message, false);
+        }
+        Err(errors) => app.show_reload_error(format!(
+            "Reload failed, the previous configuration is kept:\n{}",
+            errors
+        )),
+    }
 }
 
 /// Run the app with a generic terminal backend. Necessary so that we e.g. can use stderr instead of stdout for the terminal backend. Opens the possibility to switch crossterm with a different backend
 async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App,
-) -> Result<(), Box<dyn std::error::Error>> {
+    args: &CliArgs,
+) -> anyhow::Result<()> {
     // Create a channel to communicate between threads
-    let (tx_output, mut rx_output) = mpsc::channel::<String>(5); // for stdout/stderr from command to UI
-    let (tx_input, rx_input) = mpsc::channel::<String>(5); // for user input from UI to command stdin
+    let (tx_repos, mut rx_repos) = mpsc::channel::<Vec<RepoStatus>>(1); // results of the repository scans
+    let (tx_remote, mut rx_remote) = mpsc::channel::<Result<RepoListing, String>>(1); // repositories of the forge
+    let (tx_bulk, mut rx_bulk) = mpsc::channel::<(usize, Outcome, String)>(16); // results of the bulk operations
+    let (tx_review, mut rx_review) = mpsc::channel::<(PathBuf, ReviewUpdate)>(1); // results of the git commands of the review
 
-    // Arc/Mutex necessary sind the receiver needs to be moved to the async command thread in the
-    // ui-loop
-    let rx_input_arc = Arc::new(Mutex::new(rx_input));
+    // reload the config and the task catalog when they are edited
+    let mut watcher = FileWatcher::new(watched_files(args, &app.config));
 
     loop {
-        // read messages from the async command process and update the display
-        while let Ok(msg) = rx_output.try_recv() {
-            app.items.push(msg.clone());
+        if watcher.changed() {
+            reload(app, args);
+            watcher = FileWatcher::new(watched_files(args, &app.config));
+        }
+
+        // read messages from the async command processes and update the display
+        app.sessions.receive();
+
+        // answer the password prompts of sudo with the cached password or ask for it
+        app.password
+            .expire(Duration::from_secs(app.config.password_timeout));
+        let mut waiting = None; // task in the background that waits for the password
+        for (i, session) in app.sessions.sessions.iter_mut().enumerate() {
+            let Some(retry) = session.run.password_request.take() else {
+                continue;
+            };
+            if retry {
+                app.password.clear();
+            }
+            match app.password.get() {
+                Some(password) => {
+                    let line = password.with_byte(b'\n');
+                    session.send_input(InputMessage::Secret(line));
+                }
+                None => {
+                    session.run.ask_password(retry);
+                    if i != app.sessions.selected {
+                        waiting = Some(session.run.name.clone());
+                    }
+                }
+            }
+        }
+        if let Some(name) = waiting {
+            app.show_toast(format!("{} waits for the sudo password", name), false);
+        }
+
+        // scan the repositories in the background while the dashboard is shown
+        while let Ok(repos) = rx_repos.try_recv() {
+            app.dashboard.update(repos);
+        }
+        if let CurrentScreen::Dashboard = app.current_screen {
+            if app.dashboard.needs_refresh() {
+                app.dashboard.start_scan();
+                let dir = PathBuf::from(expand_home(&app.config.git_repo_dir));
+                spawn_scan(dir, app.config.git_backend.backend(), tx_repos.clone());
+            }
+        }
+
+        // run the git commands of the review in the background, one after another
+        while let Ok((repo, update)) = rx_review.try_recv() {
+            if let Some(review) = app.review.as_mut().filter(|review| review.repo == repo) {
+                review.update(update);
+            }
+        }
+        if let Some(review) = app.review.as_mut() {
+            if let Some(job) = review.next_job() {
+                review.spawn_job(job, tx_review.clone());
+            }
+        }
+
+        // fetch the repositories of the forge in the background
+        while let Ok(result) = rx_remote.try_recv() {
+            app.clone_list.update(result);
+            if let Some(reason) = app.clone_list.skipped.first() {
+                let message = format!(
+                    "Skipped {} invalid entries of the forge: {}",
+                    app.clone_list.skipped.len(),
+                    reason
+                );
+                app.show_toast(message, false);
+            }
+        }
+        if app.clone_list.fetch && !app.clone_list.loading {
+            app.clone_list.fetch = false;
+            app.clone_list.loading = true;
+            spawn_list_repos(
+                app.config.forge.clone(),
+                app.config.name.clone(),
+                PathBuf::from(expand_home(&app.config.git_repo_dir)),
+                tx_remote.clone(),
+            );
+        }
+
+        // run the bulk operation in the background, the repositories are scanned again afterwards
+        while let Ok((index, outcome, reason)) = rx_bulk.try_recv() {
+            if let Some(bulk) = app.bulk.as_mut() {
+                bulk.update(index, outcome, reason);
+                if !bulk.is_running() {
+                    app.dashboard.refresh();
+                }
+            }
+        }
+        if let Some(bulk) = app.bulk.as_mut().filter(|bulk| !bulk.started) {
+            bulk.started = true;
+            spawn_bulk(
+                bulk.op,
+                bulk.repos.clone(),
+                app.config.git_backend.backend(),
+                tx_bulk.clone(),
+            );
         }
 
         // redraw the ui
@@ -82,69 +323,273 @@ async fn run_app<B: Backend>(
                     continue;
                 }
 
+                // after Ctrl+V the next Ctrl key is sent to the task instead of being handled by
+                // the app, e.g. Ctrl+S or Ctrl+W for an editor. Any other key ends the Ctrl+V.
+                if let CurrentScreen::Input = app.current_screen {
+                    let literal = app.sessions.active_mut().filter(|session| {
+                        session.run.input.active && session.run.input.literal_next
+                    });
+                    if let Some(session) = literal {
+                        match key.code {
+                            KeyCode::Char(c) if key.modifiers == KeyModifiers::CONTROL => {
+                                if let Some(message) = session.run.input.control_key(c) {
+                                    session.send_input(message);
+                                }
+                                continue;
+                            }
+                            _ => session.run.input.literal_next = false,
+                        }
+                    }
+                }
+
                 // Commands that should be available anywhere
                 // close the app
                 if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('q') {
+                    // do not leave orphaned processes behind
+                    app.sessions.shutdown().await;
                     return Ok(());
                 }
                 if key.code == KeyCode::Esc {
                     app.back_to_start();
                 }
-                if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('c') {
-                    app.activate_input_field = true;
-                    let tmp_command = format!("read varname").to_string();
-
-                    let mut command: Vec<String> = vec![tmp_command];
-                    command.append(&mut vec![format!("echo $varname").to_string()]);
-
-                    // Spawn a thread to run the Bash command asynchronously
-                    // clone of command necessary to print it on error
-                    let tx = tx_output.clone();
-                    let rx = Arc::clone(&rx_input_arc);
-
-                    let _handle = tokio::spawn(run_command(tx, rx, command));
-                }
                 if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('s') {
-                    app.show_password_prompt = true;
-                    app.activate_input_field = true;
+                    if let Some(session) = app.sessions.active_mut() {
+                        session.run.input.masked = true;
+                        session.run.input.active = true;
+                    }
+                }
+                // cancel the task of the selected session, switch between the sessions of the
+                // tab bar and close finished ones. Only where the output of the session is shown,
+                // so Ctrl+C never cancels a task that is not in sight.
+                if key.modifiers == KeyModifiers::CONTROL
+                    && matches!(
+                        app.current_screen,
+                        CurrentScreen::Start | CurrentScreen::Input
+                    )
+                {
+                    match key.code {
+                        KeyCode::Char('c') => {
+                            if let Some(session) = app.sessions.active() {
+                                session.cancel();
+                            }
+                        }
+                        KeyCode::Right => app.sessions.next(),
+                        KeyCode::Left => app.sessions.previous(),
+                        KeyCode::Char('w') => app.close_session(),
+                        _ => {}
+                    }
                 }
 
                 // handle key events according to the current screen
                 match app.current_screen {
                     CurrentScreen::Input => {
-                        // read inputs and send them via sender to the subthread
-                        if key.modifiers.is_empty() && app.activate_input_field {
+                        if key.code == KeyCode::Tab {
+                            app.toggle_focus();
+                        }
+                        if let Some(session) = app.sessions.active_mut() {
+                            // scroll the output
+                            let output = &mut session.run.output;
                             match key.code {
-                                KeyCode::Char(c) => app.input_buffer.push(c),
-                                KeyCode::Backspace => {
-                                    app.input_buffer.pop();
+                                KeyCode::PageUp => output.scroll_up(output.page()),
+                                KeyCode::PageDown => output.scroll_down(output.page()),
+                                KeyCode::Home => output.scroll_to_top(),
+                                KeyCode::End => output.scroll_to_bottom(),
+                                KeyCode::Char('f') if key.modifiers == KeyModifiers::CONTROL => {
+                                    output.toggle_follow()
                                 }
-                                KeyCode::Enter => {
-                                    let input_to_send = format!("{}\n", app.input_buffer);
-                                    tx_input.send(input_to_send).await?;
-                                    app.input_buffer.clear();
-                                    app.activate_input_field = false;
-                                    app.show_password_prompt = false;
+                                _ => {}
+                            }
+                            // read inputs and send them via sender to the subthread
+                            let input = &mut session.run.input;
+                            let mut messages = Vec::new();
+                            if input.active {
+                                let typing = key.modifiers.is_empty()
+                                    || key.modifiers == KeyModifiers::SHIFT;
+                                let control = key.modifiers == KeyModifiers::CONTROL;
+                                match key.code {
+                                    KeyCode::Char(c) if typing => input.push(c),
+                                    KeyCode::Backspace => input.pop(),
+                                    KeyCode::Enter => {
+                                        if input.sudo_password {
+                                            app.password.store(input.secret());
+                                        }
+                                        messages.push(input.take(Some(b'\n')));
+                                    }
+                                    // end of the input, the typed text is sent before
+                                    KeyCode::Char('d') if control => {
+                                        let empty = input.buffer.is_empty();
+                                        let text = input.take(None);
+                                        if !empty {
+                                            messages.push(text);
+                                        }
+                                        messages.push(InputMessage::Eof);
+                                    }
+                                    // other control characters are sent as they are, the keys
+                                    // of the app only after Ctrl+V
+                                    KeyCode::Char(c) if control => {
+                                        messages.extend(input.control_key(c));
+                                    }
+                                    // unknown key -> do nothing
+                                    _ => {}
+                                }
+                            }
+                            for message in messages {
+                                session.send_input(message);
+                            }
+                        }
+                    }
+                    CurrentScreen::Form => {
+                        if let Some(form) = app.form.as_mut() {
+                            match key.code {
+                                KeyCode::Tab | KeyCode::Down => form.next(),
+                                KeyCode::BackTab | KeyCode::Up => form.previous(),
+                                KeyCode::Left => form.change(-1),
+                                KeyCode::Right => form.change(1),
+                                KeyCode::Backspace => form.backspace(),
+                                KeyCode::Char(c)
+                                    if key.modifiers.is_empty()
+                                        || key.modifiers == KeyModifiers::SHIFT =>
+                                {
+                                    form.input_char(c)
                                 }
-                                // unknown key -> do nothing
+                                KeyCode::Enter => app.submit_form(),
                                 _ => {}
                             }
                         }
                     }
                     CurrentScreen::Start => {
-                        if key.modifiers.is_empty() && !app.activate_input_field {
+                        if key.modifiers.is_empty() {
                             match key.code {
+                                KeyCode::Tab => app.toggle_focus(),
                                 KeyCode::Down => app.next(),
                                 KeyCode::Up => app.previous(),
+                                KeyCode::Right => app.expand(true),
+                                KeyCode::Left => app.expand(false),
                                 KeyCode::Enter => app.select(),
+                                KeyCode::Char('g') => app.open_dashboard(),
+                                KeyCode::Char('s') if !app.sessions.sessions.is_empty() => {
+                                    app.current_screen = CurrentScreen::Sessions
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    CurrentScreen::Dashboard => {
+                        if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT {
+                            match key.code {
+                                KeyCode::Down => app.dashboard.next(),
+                                KeyCode::Up => app.dashboard.previous(),
+                                KeyCode::Char('r') => app.dashboard.refresh(),
+                                KeyCode::Char('c') => app.open_clone_list(),
+                                KeyCode::Char('f') => app.start_bulk(BulkOp::Fetch),
+                                KeyCode::Char('u') => app.start_bulk(BulkOp::Pull),
+                                KeyCode::Char('P') => app.start_bulk(BulkOp::Push),
+                                KeyCode::Char('d') => app.start_bulk(BulkOp::Dirty),
+                                KeyCode::Char('b') if app.bulk.is_some() => {
+                                    app.current_screen = CurrentScreen::Bulk
+                                }
+                                KeyCode::Char('p') => {
+                                    if let Some(repo) =
+                                        app.dashboard.repos.get(app.dashboard.selected)
+                                    {
+                                        app.open_review(repo.path.clone());
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    CurrentScreen::Review => {
+                        if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('p')
+                        {
+                            app.commit_and_push();
+                        } else if let Some(review) = app.review.as_mut() {
+                            if key.code == KeyCode::Tab {
+                                review.focus = match review.focus {
+                                    ReviewFocus::Files => ReviewFocus::Message,
+                                    ReviewFocus::Message => ReviewFocus::Files,
+                                };
+                            }
+                            match review.focus {
+                                ReviewFocus::Files if key.modifiers.is_empty() => match key.code {
+                                    KeyCode::Down => review.next(),
+                                    KeyCode::Up => review.previous(),
+                                    KeyCode::Char(' ') => review.toggle_stage(),
+                                    KeyCode::Char('r') => review.refresh(),
+                                    KeyCode::PageDown => review.scroll_diff(10),
+                                    KeyCode::PageUp => review.scroll_diff(-10),
+                                    _ => {}
+                                },
+                                ReviewFocus::Message => {
+                                    let editor = &mut review.message;
+                                    match key.code {
+                                        KeyCode::Char(c)
+                                            if key.modifiers.is_empty()
+                                                || key.modifiers == KeyModifiers::SHIFT =>
+                                        {
+                                            editor.insert_char(c)
+                                        }
+                                        KeyCode::Enter => editor.newline(),
+                                        KeyCode::Backspace => editor.backspace(),
+                                        KeyCode::Left => editor.left(),
+                                        KeyCode::Right => editor.right(),
+                                        KeyCode::Up => editor.up(),
+                                        KeyCode::Down => editor.down(),
+                                        KeyCode::Home => editor.home(),
+                                        KeyCode::End => editor.end(),
+                                        _ => {}
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    CurrentScreen::Bulk => {
+                        if let Some(bulk) = app.bulk.as_mut() {
+                            match key.code {
+                                KeyCode::Down => bulk.next(),
+                                KeyCode::Up => bulk.previous(),
+                                _ => {}
+                            }
+                        }
+                    }
+                    CurrentScreen::Sessions => {
+                        if key.modifiers.is_empty() {
+                            match key.code {
+                                KeyCode::Down => app.sessions.next(),
+                                KeyCode::Up => app.sessions.previous(),
+                                KeyCode::Enter => app.current_screen = CurrentScreen::Input,
+                                KeyCode::Char('c') => {
+                                    if let Some(session) = app.sessions.active() {
+                                        session.cancel();
+                                    }
+                                }
+                                KeyCode::Char('x') => app.close_session(),
+                                _ => {}
+                            }
+                        }
+                    }
+                    CurrentScreen::Clone => {
+                        if key.modifiers.is_empty() {
+                            match key.code {
+                                KeyCode::Down => app.clone_list.next(),
+                                KeyCode::Up => app.clone_list.previous(),
+                                KeyCode::Char(' ') => app.clone_list.toggle(),
+                                KeyCode::Char('a') => app.clone_list.toggle_all(),
+                                KeyCode::Char('r') => app.clone_list.fetch = true,
+                                KeyCode::Enter => app.clone_marked(),
                                 _ => {}
                             }
                         }
                     }
-                    // screen not defined
-                    _ => {}
                 }
             }
         }
+
+        // start the selected task in a new session as soon as all of its parameters are entered
+        if let Some((name, steps, options)) = app.take_ready_task() {
+            let session = Session::spawn(name, steps, options, app.config.scrollback);
+            app.sessions.push(session);
+        }
     }
 }
@@ -0,0 +1,346 @@
+use crate::tasks::Task;
+
+/// Node of the menu tree in the start screen. Categories are built from the `category` of the
+/// tasks, nested categories are separated by a `/` (e.g. `system/services`).
+pub enum MenuNode {
+    Category {
+        name: String,
+        expanded: bool,
+        children: Vec<MenuNode>,
+    },
+    // index of the task in App.tasks
+    Task(usize),
+}
+
+/// Entry of the flattened list of nodes that is currently shown. The path contains the indices
+/// of the node starting at the root of the tree.
+pub struct VisibleNode {
+    pub depth: usize,
+    pub path: Vec<usize>,
+}
+
+pub struct Menu {
+    pub nodes: Vec<MenuNode>,
+    // path of the category that is currently opened, empty for the root
+    pub current: Vec<usize>,
+}
+
+impl Menu {
+    pub fn from_tasks(tasks: &[Task]) -> Menu {
+        let mut nodes = Vec::new();
+        for (i, task) in tasks.iter().enumerate() {
+            let categories: Vec<&str> = task
+                .category
+                .split('/')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .collect();
+            insert_task(&mut nodes, &categories, i);
+        }
+        Menu {
+            nodes,
+            current: Vec::new(),
+        }
+    }
+
+    pub fn node(&self, path: &[usize]) -> Option<&MenuNode> {
+        let (first, rest) = path.split_first()?;
+        let mut node = self.nodes.get(*first)?;
+        for i in rest {
+            match node {
+                MenuNode::Category { children, .. } => node = children.get(*i)?,
+                MenuNode::Task(_) => return None,
+            }
+        }
+        Some(node)
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> Option<&mut MenuNode> {
+        let (first, rest) = path.split_first()?;
+        let mut node = self.nodes.get_mut(*first)?;
+        for i in rest {
+            match node {
+                MenuNode::Category { children, .. } => node = children.get_mut(*i)?,
+                MenuNode::Task(_) => return None,
+            }
+        }
+        Some(node)
+    }
+
+    /// Children of the category at the given path
+    fn children(&self, path: &[usize]) -> &[MenuNode] {
+        if path.is_empty() {
+            return &self.nodes;
+        }
+        match self.node(path) {
+            Some(MenuNode::Category { children, .. }) => children,
+            _ => &[],
+        }
+    }
+
+    /// Flatten the opened category. Children of expanded categories are shown below them.
+    pub fn visible(&self) -> Vec<VisibleNode> {
+        let mut visible = Vec::new();
+        self.collect_visible(&self.current, 0, &mut visible);
+        visible
+    }
+
+    fn collect_visible(&self, path: &[usize], depth: usize, visible: &mut Vec<VisibleNode>) {
+        for (i, node) in self.children(path).iter().enumerate() {
+            let mut child_path = path.to_vec();
+            child_path.push(i);
+            visible.push(VisibleNode {
+                depth,
+                path: child_path.clone(),
+            });
+            if let MenuNode::Category { expanded: true, .. } = node {
+                self.collect_visible(&child_path, depth + 1, visible);
+            }
+        }
+    }
+
+    /// Open the category at the given path. Returns false if the path is not a category.
+    pub fn enter(&mut self, path: &[usize]) -> bool {
+        if let Some(MenuNode::Category { .. }) = self.node(path) {
+            self.current = path.to_vec();
+            return true;
+        }
+        false
+    }
+
+    /// Go up one level. Returns the path of the category that was left.
+    pub fn leave(&mut self) -> Option<Vec<usize>> {
+        if self.current.is_empty() {
+            return None;
+        }
+        let left = self.current.clone();
+        self.current.pop();
+        Some(left)
+    }
+
+    /// Expand or collapse the category at the given path
+    pub fn set_expanded(&mut self, path: &[usize], value: bool) {
+        if let Some(MenuNode::Category { expanded, .. }) = self.node_mut(path) {
+            *expanded = value;
+        }
+    }
+
+    /// Names of the categories (and the task) from the root to the node at the given path. Unlike
+    /// the path, the names stay valid when the menu is rebuilt from changed tasks.
+    pub fn names(&self, path: &[usize], tasks: &[Task]) -> Vec<String> {
+        (1..=path.len())
+            .filter_map(|len| match self.node(&path[..len]) {
+                Some(MenuNode::Category { name, .. }) => Some(name.clone()),
+                Some(MenuNode::Task(i)) => tasks.get(*i).map(|task| task.name.clone()),
+                None => None,
+            })
+            .collect()
+    }
+
+    /// Path of the node with the given names, see `names`
+    pub fn find(&self, names: &[String], tasks: &[Task]) -> Option<Vec<usize>> {
+        let mut path = Vec::new();
+        for name in names {
+            let i = self.children(&path).iter().position(|node| match node {
+                MenuNode::Category { name: n, .. } => n == name,
+                MenuNode::Task(i) => tasks.get(*i).is_some_and(|task| &task.name == name),
+            })?;
+            path.push(i);
+        }
+        Some(path)
+    }
+
+    /// Paths of all expanded categories, also of those below a collapsed category (e.g. the opened
+    /// one)
+    pub fn expanded(&self) -> Vec<Vec<usize>> {
+        let mut expanded = Vec::new();
+        self.collect_expanded(&[], &mut expanded);
+        expanded
+    }
+
+    fn collect_expanded(&self, path: &[usize], expanded: &mut Vec<Vec<usize>>) {
+        for (i, node) in self.children(path).iter().enumerate() {
+            if let MenuNode::Category {
+                expanded: is_expanded,
+                ..
+            } = node
+            {
+                let mut child_path = path.to_vec();
+                child_path.push(i);
+                self.collect_expanded(&child_path, expanded);
+                if *is_expanded {
+                    expanded.push(child_path);
+                }
+            }
+        }
+    }
+
+    /// Names of the categories from the root to the opened category
+    pub fn breadcrumb(&self) -> Vec<&str> {
+        (1..=self.current.len())
+            .filter_map(|len| match self.node(&self.current[..len]) {
+                Some(MenuNode::Category { name, .. }) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Insert the task below the given categories and create missing categories on the way.
+/// Categories are placed in front of the tasks of the same level.
+fn insert_task(nodes: &mut Vec<MenuNode>, categories: &[&str], task: usize) {
+    let Some((category, rest)) = categories.split_first() else {
+        nodes.push(MenuNode::Task(task));
+        return;
+    };
+    let position = nodes.iter().position(
+        |node| matches!(node, MenuNode::Category { name, .. } if name.as_str() == *category),
+    );
+    let position = match position {
+        Some(position) => position,
+        None => {
+            let first_task = nodes
+                .iter()
+                .position(|node| matches!(node, MenuNode::Task(_)))
+                .unwrap_or(nodes.len());
+            nodes.insert(
+                first_task,
+                MenuNode::Category {
+                    name: category.to_string(),
+                    expanded: false,
+                    children: Vec::new(),
+                },
+            );
+            first_task
+        }
+    };
+    if let MenuNode::Category { children, .. } = &mut nodes[position] {
+        insert_task(children, rest, task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tasks with the given names and categories
+    fn tasks(entries: &[(&str, &str)]) -> Vec<Task> {
+        entries
+            .iter()
+            .map(|(name, category)| {
+                toml::from_str(&format!(
+                    "name = {:?}\ncategory = {:?}\nsteps = []",
+                    name, category
+                ))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    /// Name of the category or task at the given path
+    fn name(menu: &Menu, path: &[usize], tasks: &[Task]) -> String {
+        match menu.node(path) {
+            Some(MenuNode::Category { name, .. }) => name.clone(),
+            Some(MenuNode::Task(i)) => tasks[*i].name.clone(),
+            None => panic!("no node at {:?}", path),
+        }
+    }
+
+    /// Names of the visible nodes, indented by their depth
+    fn shown(menu: &Menu, tasks: &[Task]) -> Vec<String> {
+        menu.visible()
+            .iter()
+            .map(|node| {
+                format!(
+                    "{}{}",
+                    "  ".repeat(node.depth),
+                    name(menu, &node.path, tasks)
+                )
+            })
+            .collect()
+    }
+
+    fn sample() -> Vec<Task> {
+        tasks(&[
+            ("top", ""),
+            ("status", "system/services"),
+            ("update", "system"),
+            ("clone", "git"),
+            ("restart", " system / services "),
+        ])
+    }
+
+    #[test]
+    fn categories_are_nested_and_shown_before_tasks() {
+        let tasks = sample();
+        let menu = Menu::from_tasks(&tasks);
+        assert_eq!(shown(&menu, &tasks), ["system", "git", "top"]);
+        assert_eq!(name(&menu, &[0, 0], &tasks), "services");
+        assert_eq!(name(&menu, &[0, 0, 0], &tasks), "status");
+        // the spaces around the names are trimmed, both tasks are in the same category
+        assert_eq!(name(&menu, &[0, 0, 1], &tasks), "restart");
+        assert_eq!(name(&menu, &[0, 1], &tasks), "update");
+        assert!(menu.node(&[0, 2]).is_none());
+    }
+
+    #[test]
+    fn expanded_categories_show_their_children() {
+        let tasks = sample();
+        let mut menu = Menu::from_tasks(&tasks);
+        menu.set_expanded(&[0], true);
+        menu.set_expanded(&[0, 0], true);
+        assert_eq!(
+            shown(&menu, &tasks),
+            [
+                "system",
+                "  services",
+                "    status",
+                "    restart",
+                "  update",
+                "git",
+                "top"
+            ]
+        );
+        assert_eq!(menu.expanded(), [vec![0, 0], vec![0]]);
+        menu.set_expanded(&[0], false);
+        assert_eq!(shown(&menu, &tasks), ["system", "git", "top"]);
+        // services stays expanded below the collapsed category
+        assert_eq!(menu.expanded(), [vec![0, 0]]);
+    }
+
+    #[test]
+    fn enter_and_leave_categories() {
+        let tasks = sample();
+        let mut menu = Menu::from_tasks(&tasks);
+        // tasks can not be entered
+        assert!(!menu.enter(&[2]));
+        assert!(menu.enter(&[0]));
+        assert!(menu.enter(&[0, 0]));
+        assert_eq!(menu.breadcrumb(), ["system", "services"]);
+        assert_eq!(shown(&menu, &tasks), ["status", "restart"]);
+        assert_eq!(menu.leave(), Some(vec![0, 0]));
+        assert_eq!(shown(&menu, &tasks), ["services", "update"]);
+        assert_eq!(menu.leave(), Some(vec![0]));
+        assert_eq!(menu.leave(), None);
+        assert!(menu.breadcrumb().is_empty());
+    }
+
+    #[test]
+    fn names_find_the_node_in_a_rebuilt_menu() {
+        let tasks = sample();
+        let menu = Menu::from_tasks(&tasks);
+        let names = menu.names(&[0, 0, 1], &tasks);
+        assert_eq!(names, ["system", "services", "restart"]);
+
+        // a new category moves the nodes, the names still lead to the same task
+        let mut changed = sample();
+        changed.insert(0, self::tasks(&[("backup", "backup")]).remove(0));
+        let rebuilt = Menu::from_tasks(&changed);
+        let path = rebuilt.find(&names, &changed).unwrap();
+        assert_eq!(path, [1, 0, 1]);
+        assert!(
+            matches!(rebuilt.node(&path), Some(MenuNode::Task(i)) if changed[*i].name == "restart")
+        );
+        assert_eq!(rebuilt.find(&["missing".to_string()], &changed), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+
+use ratatui::text::Line;
+
+use crate::{ansi::AnsiParser, functions::Stream};
+
+// number of lines that are kept if nothing else is configured
+pub const DEFAULT_SCROLLBACK: usize = 10_000;
+
+/// Output of a task. The buffer keeps at most `capacity` lines, older lines are dropped. The
+/// view either follows the end of the output or stays at the position the user scrolled to.
+pub struct OutputBuffer {
+    pub lines: VecDeque<(Stream, Line<'static>)>,
+    pub capacity: usize,
+    pub follow: bool,            // scroll automatically to the newest line
+    scroll: usize,               // index of the first shown line if follow is disabled
+    pub viewport_height: usize,  // number of lines that fit into the output pane, set by the ui
+    partial: bool,               // the last line is unfinished and will be replaced
+    ansi: AnsiParser,            // style of the escape sequences of the output
+    ansi_line_start: AnsiParser, // style at the start of the last line
+}
+
+impl OutputBuffer {
+    pub fn new(capacity: usize) -> OutputBuffer {
+        OutputBuffer {
+            lines: VecDeque::new(),
+            capacity: capacity.max(1),
+            follow: true,
+            scroll: 0,
+            viewport_height: 0,
+            partial: false,
+            ansi: AnsiParser::default(),
+            ansi_line_start: AnsiParser::default(),
+        }
+    }
+
+    /// Add a line of output. An unfinished (partial) line is replaced by the next line. Escape
+    /// sequences of the line are converted to styles.
+    pub fn push(&mut self, stream: Stream, line: &str, partial: bool) {
+        if self.partial {
+            // the replaced line has to be parsed again with the style it started with
+            self.lines.pop_back();
+            self.ansi = self.ansi_line_start;
+        }
+        self.ansi_line_start = self.ansi;
+        let line = self.ansi.parse_line(line);
+        self.lines.push_back((stream, line));
+        self.partial = partial;
+        self.trim();
+    }
+
+    /// Change the number of lines that are kept, the oldest lines are dropped right away
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.trim();
+    }
+
+    /// Drop the oldest lines beyond the capacity and keep the shown lines in place
+    fn trim(&mut self) {
+        while self.lines.len() > self.capacity {
+            self.lines.pop_front();
+            self.scroll = self.scroll.saturating_sub(1);
+        }
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.lines.len().saturating_sub(self.viewport_height)
+    }
+
+    /// Index of the first line that is shown in the output pane
+    pub fn top(&self) -> usize {
+        if self.follow {
+            self.max_scroll()
+        } else {
+            self.scroll.min(self.max_scroll())
+        }
+    }
+
+    /// Scroll towards older lines. Following the output is stopped.
+    pub fn scroll_up(&mut self, amount: usize) {
+        self.scroll = self.top().saturating_sub(amount);
+        self.follow = false;
+    }
+
+    /// Scroll towards newer lines. Reaching the end does not enable following the output again.
+    pub fn scroll_down(&mut self, amount: usize) {
+        self.scroll = (self.top() + amount).min(self.max_scroll());
+        self.follow = false;
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.scroll = 0;
+        self.follow = false;
+    }
+
+    /// Jump to the newest line and follow the output
+    pub fn scroll_to_bottom(&mut self) {
+        self.follow = true;
+    }
+
+    pub fn toggle_follow(&mut self) {
+        if self.follow {
+            self.scroll = self.top();
+        }
+        self.follow = !self.follow;
+    }
+
+    /// Number of lines that are scrolled by PageUp/PageDown
+    pub fn page(&self) -> usize {
+        self.viewport_height.saturating_sub(1).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Buffer with the lines 0..count and a pane of `height` lines
+    fn buffer(capacity: usize, height: usize, count: usize) -> OutputBuffer {
+        let mut output = OutputBuffer::new(capacity);
+        output.viewport_height = height;
+        for i in 0..count {
+            output.push(Stream::Stdout, &i.to_string(), false);
+        }
+        output
+    }
+
+    fn text(output: &OutputBuffer, index: usize) -> String {
+        output.lines[index].1.to_string()
+    }
+
+    #[test]
+    fn oldest_lines_are_dropped_at_capacity() {
+        let output = buffer(3, 2, 5);
+        assert_eq!(output.lines.len(), 3);
+        assert_eq!(text(&output, 0), "2");
+        assert_eq!(text(&output, 2), "4");
+        assert_eq!(OutputBuffer::new(0).capacity, 1);
+    }
+
+    #[test]
+    fn smaller_capacity_drops_lines_right_away() {
+        let mut output = buffer(100, 5, 30);
+        output.scroll_up(10);
+        assert_eq!(output.top(), 15);
+        output.set_capacity(20);
+        assert_eq!(output.lines.len(), 20);
+        assert_eq!(text(&output, 0), "10");
+        // the shown lines stay in place
+        assert_eq!(text(&output, output.top()), "15");
+        output.set_capacity(0);
+        assert_eq!(output.lines.len(), 1);
+        assert_eq!(text(&output, 0), "29");
+    }
+
+    #[test]
+    fn partial_lines_are_replaced() {
+        let mut output = buffer(10, 5, 1);
+        output.push(Stream::Stdout, "10%", true);
+        output.push(Stream::Stdout, "\x1b[1m50%", true);
+        output.push(Stream::Stdout, "100%", false);
+        output.push(Stream::Stdout, "done", false);
+        assert_eq!(output.lines.len(), 3);
+        assert_eq!(text(&output, 1), "100%");
+        // the style of the replaced line is not carried over
+        assert_eq!(output.lines[2].1.spans[0].style, Default::default());
+    }
+
+    #[test]
+    fn follow_shows_the_newest_lines() {
+        let mut output = buffer(100, 10, 4);
+        assert_eq!(output.top(), 0);
+        for i in 4..25 {
+            output.push(Stream::Stderr, &i.to_string(), false);
+        }
+        assert_eq!(output.top(), 15);
+    }
+
+    #[test]
+    fn scrolling_stops_following_and_is_clamped() {
+        let mut output = buffer(100, 10, 30);
+        output.scroll_up(5);
+        assert!(!output.follow);
+        assert_eq!(output.top(), 15);
+        output.push(Stream::Stdout, "new", false);
+        assert_eq!(output.top(), 15);
+        output.scroll_up(100);
+        assert_eq!(output.top(), 0);
+        output.scroll_down(100);
+        assert_eq!(output.top(), 21);
+        assert!(!output.follow);
+        output.scroll_to_bottom();
+        output.push(Stream::Stdout, "newer", false);
+        assert_eq!(output.top(), 22);
+    }
+
+    #[test]
+    fn dropped_lines_keep_the_shown_lines_in_place() {
+        let mut output = buffer(20, 5, 20);
+        output.scroll_up(10);
+        assert_eq!(text(&output, output.top()), "5");
+        output.push(Stream::Stdout, "20", false);
+        output.push(Stream::Stdout, "21", false);
+        assert_eq!(text(&output, output.top()), "5");
+        // the shown lines are dropped, the view stays at the oldest line
+        for i in 22..30 {
+            output.push(Stream::Stdout, &i.to_string(), false);
+        }
+        assert_eq!(output.top(), 0);
+        assert_eq!(text(&output, 0), "10");
+    }
+
+    #[test]
+    fn toggle_follow_keeps_the_position() {
+        let mut output = buffer(100, 10, 30);
+        output.toggle_follow();
+        output.push(Stream::Stdout, "new", false);
+        assert_eq!(output.top(), 20);
+        output.toggle_follow();
+        assert_eq!(output.top(), 21);
+        assert_eq!(output.page(), 9);
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    fs::File,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use nix::unistd::geteuid;
+
+use crate::{secret::Secret, tasks::shell_quote};
+
+/// Program that runs the steps of a task that requires root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escalation {
+    Sudo,   // reads the password from stdin, it is entered once and cached by the app
+    Doas,   // prompts on the terminal of the step
+    Pkexec, // asks the polkit agent, which prompts on the terminal if there is no graphical one
+}
+
+impl Escalation {
+    pub fn program(self) -> &'static str {
+        match self {
+            Escalation::Sudo => "sudo",
+            Escalation::Doas => "doas",
+            Escalation::Pkexec => "pkexec",
+        }
+    }
+
+    /// doas and pkexec only prompt on a terminal, their steps run in a pseudo-terminal
+    pub fn needs_terminal(self) -> bool {
+        self != Escalation::Sudo
+    }
+
+    /// Arguments of the program to run the shell command as root. With a prompt, sudo prints it
+    /// as a line of its own and reads the password from stdin. sudo is asked to keep the
+    /// environment variables in `envs`. doas and pkexec clear the environment, so the variables
+    /// with a value are set again with `env NAME=value` after them. These values end up in the
+    /// arguments of a process and are visible to every user in the process list. The others
+    /// (passwords) only reach the command if doas is configured to keep them (keepenv/setenv in
+    /// doas.conf). pkexec starts the command in the home directory of root, so the command
+    /// changes to `dir` first.
+    pub fn args(
+        self,
+        command: &str,
+        prompt: Option<&str>,
+        envs: &[(&str, Option<&str>)],
+        dir: Option<&Path>,
+    ) -> Vec<String> {
+        let command = match (self, dir) {
+            (Escalation::Pkexec, Some(dir)) => {
+                format!("cd {} && {}", shell_quote(&dir.to_string_lossy()), command)
+            }
+            _ => command.to_string(),
+        };
+        let mut assignments = Vec::new();
+        if self != Escalation::Sudo {
+            for (name, value) in envs {
+                if let Some(value) = value {
+                    assignments.push(format!("{}={}", name, value));
+                }
+            }
+        }
+        let mut args = Vec::new();
+        if let (Escalation::Sudo, Some(prompt)) = (self, prompt) {
+            args.extend(["-S".to_string(), "-p".to_string(), format!("{}\n", prompt)]);
+        }
+        if self == Escalation::Sudo && !envs.is_empty() {
+            let names: Vec<&str> = envs.iter().map(|(name, _)| *name).collect();
+            args.push(format!("--preserve-env={}", names.join(",")));
+        }
+        if self != Escalation::Pkexec {
+            args.push("--".to_string());
+        }
+        if !assignments.is_empty() {
+            args.push("env".to_string());
+            args.extend(assignments);
+        }
+        args.extend(["bash".to_string(), "-c".to_string(), command]);
+        args
+    }
+}
+
+/// How the steps of a task that requires root are run, None if the app runs as root already
+pub fn root_access() -> Result<Option<Escalation>, String> {
+    if geteuid().is_root() {
+        return Ok(None);
+    }
+    [Escalation::Sudo, Escalation::Doas, Escalation::Pkexec]
+        .into_iter()
+        .find(|tool| in_path(tool.program()))
+        .map(Some)
+        .ok_or_else(|| "requires root, but none of sudo, doas or pkexec is installed".to_string())
+}
+
+/// The program is an executable file in one of the directories of $PATH
+fn in_path(program: &str) -> bool {
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Line that sudo prints when it asks for the password. It contains a random part, so the output
+/// of a command can not pretend to be the prompt to get the password.
+pub fn password_prompt() -> String {
+    let mut nonce = [0u8; 8];
+    let random = File::open("/dev/urandom").and_then(|mut file| file.read_exact(&mut nonce));
+    if random.is_err() {
+        // still unknown to the command, which is started after the prompt was created
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos());
+        nonce = ((now as u64) ^ std::process::id() as u64).to_le_bytes();
+    }
+    let nonce: String = nonce.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("[linutil_rs] password for sudo {}", nonce)
+}
+
+/// The sudo password, kept in memory for a limited time after it was entered. It is wiped when
+/// it is cleared or expires.
+#[derive(Default)]
+pub struct PasswordCache {
+    password: Option<(Secret, Instant)>,
+}
+
+impl PasswordCache {
+    pub fn get(&self) -> Option<&Secret> {
+        self.password.as_ref().map(|(password, _)| password)
+    }
+
+    pub fn store(&mut self, password: Secret) {
+        self.password = Some((password, Instant::now()));
+    }
+
+    pub fn clear(&mut self) {
+        self.password = None;
+    }
+
+    /// Forget the password if it was entered longer than the timeout ago
+    pub fn expire(&mut self, timeout: Duration) {
+        if self
+            .password
+            .as_ref()
+            .is_some_and(|(_, stored_at)| stored_at.elapsed() >= timeout)
+        {
+            self.password = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sudo_reads_the_password_from_stdin() {
+        let args = Escalation::Sudo.args(
+            "make install",
+            Some("prompt"),
+            &[("LINUTIL_PARAM_A", None)],
+            None,
+        );
+        assert_eq!(
+            args,
+            [
+                "-S",
+                "-p",
+                "prompt\n",
+                "--preserve-env=LINUTIL_PARAM_A",
+                "--",
+                "bash",
+                "-c",
+                "make install"
+            ]
+        );
+        let args = Escalation::Sudo.args("make install", None, &[], Some(Path::new("/src")));
+        assert_eq!(args, ["--", "bash", "-c", "make install"]);
+    }
+
+    #[test]
+    fn doas_prompts_on_the_terminal() {
+        let args = Escalation::Doas.args("make install", Some("prompt"), &[], None);
+        assert_eq!(args, ["--", "bash", "-c", "make install"]);
+    }
+
+    #[test]
+    fn doas_sets_all_but_the_secret_envs() {
+        let envs = [("A", Some("it's $(id)")), ("PW", None)];
+        let args = Escalation::Doas.args("make install", None, &envs, None);
+        assert_eq!(
+            args,
+            ["--", "env", "A=it's $(id)", "bash", "-c", "make install"]
+        );
+    }
+
+    #[test]
+    fn pkexec_changes_to_the_working_dir() {
+        let args = Escalation::Pkexec.args("make install", None, &[("A", None)], None);
+        assert_eq!(args, ["bash", "-c", "make install"]);
+        let dir = Path::new("/home/me/it's here");
+        let args = Escalation::Pkexec.args("make install", None, &[("A", Some("1"))], Some(dir));
+        assert_eq!(
+            args,
+            [
+                "env",
+                "A=1",
+                "bash",
+                "-c",
+                "cd '/home/me/it'\\''s here' && make install"
+            ]
+        );
+    }
+
+    #[test]
+    fn cached_password_expires() {
+        let mut cache = PasswordCache::default();
+        cache.store(Secret::from("hunter2"));
+        cache.expire(Duration::from_secs(300));
+        assert_eq!(cache.get(), Some(&Secret::from("hunter2")));
+        cache.expire(Duration::ZERO);
+        assert!(cache.get().is_none());
+    }
+
+    #[test]
+    fn cleared_password_is_gone() {
+        let mut cache = PasswordCache::default();
+        cache.store(Secret::from("hunter2"));
+        cache.clear();
+        assert!(cache.get().is_none());
+    }
+}
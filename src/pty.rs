@@ -0,0 +1,94 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::OwnedFd,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use nix::{
+    fcntl::{fcntl, FcntlArg, OFlag},
+    pty::{openpty, Winsize},
+};
+use tokio::io::{unix::AsyncFd, AsyncRead, AsyncWrite, ReadBuf};
+
+/// Master side of a pseudo-terminal. The master can be cloned so that one clone reads the output
+/// of the child while another one writes the user input to it.
+#[derive(Clone)]
+pub struct PtyMaster {
+    fd: Arc<AsyncFd<File>>,
+}
+
+impl PtyMaster {
+    /// Open a new pseudo-terminal with the given size. Returns the master and the slave that is
+    /// used as stdin/stdout/stderr of the child process.
+    pub fn open(rows: u16, cols: u16) -> io::Result<(PtyMaster, OwnedFd)> {
+        let winsize = Winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = openpty(&winsize, None)?;
+
+        // the master is read and written asynchronously, therefore it must not block
+        let flags = OFlag::from_bits_truncate(fcntl(&pty.master, FcntlArg::F_GETFL)?);
+        fcntl(&pty.master, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+
+        // SAFETY: the File owns the master, the descriptor stays open until the AsyncFd is dropped
+        let fd = unsafe { AsyncFd::register(File::from(pty.master)) }?;
+        let master = PtyMaster { fd: Arc::new(fd) };
+        Ok((master, pty.slave))
+    }
+}
+
+impl AsyncRead for PtyMaster {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|inner| inner.get_ref().read(unfilled)) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                // Linux reports EIO on the master as soon as the last process that had the slave
+                // open has exited. This is the end of the output.
+                Ok(Err(err)) if err.raw_os_error() == Some(nix::libc::EIO) => {
+                    return Poll::Ready(Ok(()))
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for PtyMaster {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+            match guard.try_io(|inner| inner.get_ref().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    editor::TextEditor,
+    git::{stage, unstage, FileChange, GitBackend},
+    tasks::{shell_quote, Step},
+};
+
+// lines of a diff that are shown at most
+const MAX_DIFF_LINES: usize = 2_000;
+
+/// Part of the review screen that receives the keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewFocus {
+    Files,
+    Message,
+}
+
+/// Git commands of the review. They run in the background one after another, so that a slow
+/// repository does not block the user interface.
+#[derive(Debug, Clone)]
+pub enum ReviewJob {
+    // read the changed files again, after the file was staged (true) or unstaged (false)
+    Refresh {
+        toggle: Option<(FileChange, bool)>,
+        selected: Option<String>, // file that stays highlighted if it is still changed
+    },
+    // diff of the highlighted file
+    Diff(FileChange),
+}
+
+/// Result of a job of the review
+#[derive(Debug)]
+pub enum ReviewUpdate {
+    Files {
+        files: Result<Vec<FileChange>, String>,
+        selected: usize,
+        diff: Vec<String>,
+        error: Option<String>, // staging or unstaging failed
+    },
+    Diff {
+        path: String,
+        diff: Vec<String>,
+    },
+}
+
+/// Review of the changes of a repository before they are committed and pushed. Files are staged
+/// and unstaged one by one, only the staged changes are committed.
+pub struct Review {
+    pub repo: PathBuf,
+    pub files: Vec<FileChange>,
+    pub selected: usize,
+    pub diff: Vec<String>, // preview of the changes of the highlighted file
+    pub diff_scroll: u16,
+    pub message: TextEditor,
+    pub focus: ReviewFocus,
+    pub error: Option<String>, // result of the last git command if it failed
+    pub loading: bool,         // a job is running
+    jobs: VecDeque<ReviewJob>,
+    git: Arc<dyn GitBackend>,
+}
+
+impl Review {
+    pub fn new(repo: PathBuf, git: Arc<dyn GitBackend>) -> Review {
+        Review {
+            repo,
+            files: Vec::new(),
+            selected: 0,
+            diff: Vec::new(),
+            diff_scroll: 0,
+            message: TextEditor::default(),
+            focus: ReviewFocus::Files,
+            error: None,
+            loading: false,
+            jobs: VecDeque::from([ReviewJob::Refresh {
+                toggle: None,
+                selected: None,
+            }]),
+            git,
+        }
+    }
+
+    /// Read the changed files again and keep the highlighted file if it is still changed
+    pub fn refresh(&mut self) {
+        let selected = self.files.get(self.selected).map(|file| file.path.clone());
+        self.jobs.push_back(ReviewJob::Refresh {
+            toggle: None,
+            selected,
+        });
+    }
+
+    fn load_diff(&mut self) {
+        self.diff_scroll = 0;
+        self.diff.clear();
+        if let Some(file) = self.files.get(self.selected) {
+            self.jobs.push_back(ReviewJob::Diff(file.clone()));
+        }
+    }
+
+    /// Job that is started next, None while a job is running
+    pub fn next_job(&mut self) -> Option<ReviewJob> {
+        if self.loading {
+            return None;
+        }
+        let job = self.jobs.pop_front()?;
+        self.loading = true;
+        Some(job)
+    }
+
+    /// Show the result of a job. A diff is only shown if its file is still highlighted.
+    pub fn update(&mut self, update: ReviewUpdate) {
+        self.loading = false;
+        match update {
+            ReviewUpdate::Files {
+                files,
+                selected,
+                diff,
+                error,
+            } => {
+                match files {
+                    Ok(files) => {
+                        self.files = files;
+                        self.error = error;
+                    }
+                    Err(err) => {
+                        self.files.clear();
+                        self.error = Some(err);
+                    }
+                }
+                self.selected = selected;
+                self.diff = diff;
+                self.diff_scroll = 0;
+            }
+            ReviewUpdate::Diff { path, diff } => {
+                if self
+                    .files
+                    .get(self.selected)
+                    .is_some_and(|file| file.path == path)
+                {
+                    self.diff = diff;
+                }
+            }
+        }
+    }
+
+    pub fn next(&mut self) {
+        if !self.files.is_empty() {
+            self.selected = (self.selected + 1) % self.files.len();
+            self.load_diff();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.files.is_empty() {
+            self.selected = (self.selected + self.files.len() - 1) % self.files.len();
+            self.load_diff();
+        }
+    }
+
+    /// Stage the highlighted file or, if it is staged completely, unstage it
+    pub fn toggle_stage(&mut self) {
+        let Some(file) = self.files.get(self.selected) else {
+            return;
+        };
+        let stage = !file.staged || file.unstaged;
+        self.jobs.push_back(ReviewJob::Refresh {
+            toggle: Some((file.clone(), stage)),
+            selected: Some(file.path.clone()),
+        });
+    }
+
+    /// Run the job on a thread that may block and send its result together with the repository
+    pub fn spawn_job(&self, job: ReviewJob, tx: Sender<(PathBuf, ReviewUpdate)>) {
+        let repo = self.repo.clone();
+        let git = Arc::clone(&self.git);
+        tokio::task::spawn_blocking(move || {
+            let update = run_job(&repo, git.as_ref(), job);
+            let _ = tx.blocking_send((repo, update));
+        });
+    }
+
+    pub fn scroll_diff(&mut self, lines: i32) {
+        let max = self.diff.len().saturating_sub(1) as i32;
+        self.diff_scroll = (self.diff_scroll as i32 + lines).clamp(0, max) as u16;
+    }
+
+    /// A job is running or waiting, the shown files may be outdated
+    pub fn is_busy(&self) -> bool {
+        self.loading || !self.jobs.is_empty()
+    }
+
+    pub fn has_staged_files(&self) -> bool {
+        self.files.iter().any(|file| file.staged)
+    }
+
+    /// Steps that commit the staged files with the entered message and push the commit
+    pub fn commit_steps(&self) -> Vec<Step> {
+        vec![
+            Step {
+                run: format!("git commit -m {}", shell_quote(&self.message.text())),
+                name: Some("git commit".to_string()),
+                continue_on_error: false,
+            },
+            Step {
+                run: "git push".to_string(),
+                name: Some("git push".to_string()),
+                continue_on_error: false,
+            },
+        ]
+    }
+}
+
+fn run_job(repo: &Path, git: &dyn GitBackend, job: ReviewJob) -> ReviewUpdate {
+    match job {
+        ReviewJob::Diff(file) => ReviewUpdate::Diff {
+            diff: diff_lines(repo, git, &file),
+            path: file.path,
+        },
+        ReviewJob::Refresh { toggle, selected } => {
+            let error = toggle.and_then(|(file, stage_file)| {
+                let result = if stage_file {
+                    stage(repo, &file)
+                } else {
+                    unstage(repo, &file)
+                };
+                result.err()
+            });
+            let files = git.changed_files(repo);
+            let changed = files.as_deref().unwrap_or_default();
+            let selected = selected
+                .and_then(|path| changed.iter().position(|file| file.path == path))
+                .unwrap_or(0);
+            let diff = changed
+                .get(selected)
+                .map(|file| diff_lines(repo, git, file))
+                .unwrap_or_default();
+            ReviewUpdate::Files {
+                files,
+                selected,
+                diff,
+                error,
+            }
+        }
+    }
+}
+
+/// Lines of the diff of the file, shortened to MAX_DIFF_LINES
+fn diff_lines(repo: &Path, git: &dyn GitBackend, file: &FileChange) -> Vec<String> {
+    match git.diff(repo, file) {
+        Ok(diff) => diff
+            .lines()
+            .take(MAX_DIFF_LINES)
+            .map(String::from)
+            .collect(),
+        Err(err) => vec![err],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use super::*;
+    use crate::{
+        git::GitBackendKind,
+        test_repo::{init_repo, isolate, run_git, TempDir},
+    };
+
+    /// Repository with a commit of a.txt and b.txt
+    fn temp_repo(temp: &TempDir) -> PathBuf {
+        let repo = temp.0.join("repo");
+        init_repo(&repo, &[("a.txt", "one\ntwo\n"), ("b.txt", "three\n")]);
+        repo
+    }
+
+    /// Run the waiting jobs one after another like the UI loop does
+    fn run_jobs(review: &mut Review) {
+        while let Some(job) = review.next_job() {
+            let update = run_job(&review.repo, review.git.as_ref(), job);
+            review.update(update);
+        }
+    }
+
+    fn file<'a>(review: &'a Review, path: &str) -> &'a FileChange {
+        review.files.iter().find(|file| file.path == path).unwrap()
+    }
+
+    #[test]
+    fn changes_are_staged_and_unstaged() {
+        for kind in [GitBackendKind::Native, GitBackendKind::Shell] {
+            let temp = TempDir::new(&format!("review-stage-{:?}", kind));
+            staged_and_unstaged(temp_repo(&temp), kind);
+        }
+    }
+
+    fn staged_and_unstaged(repo: PathBuf, kind: GitBackendKind) {
+        fs::write(repo.join("a.txt"), "one\nchanged\n").unwrap();
+        let mut review = Review::new(repo.clone(), kind.backend());
+        run_jobs(&mut review);
+        assert_eq!(review.files.len(), 1);
+        assert!(!file(&review, "a.txt").staged);
+        assert!(review.diff.iter().any(|line| line == "+changed"));
+
+        review.toggle_stage();
+        assert!(review.is_busy());
+        run_jobs(&mut review);
+        let a = file(&review, "a.txt");
+        assert!(a.staged && !a.unstaged);
+        assert!(run_git(&repo, &["diff", "--cached"]).contains("+changed"));
+        assert!(review.diff.iter().any(|line| line == "+changed"));
+
+        review.toggle_stage();
+        run_jobs(&mut review);
+        let a = file(&review, "a.txt");
+        assert!(!a.staged && a.unstaged);
+        assert_eq!(run_git(&repo, &["diff", "--cached"]), "");
+    }
+
+    #[test]
+    fn renames_are_staged_and_unstaged_with_both_paths() {
+        for kind in [GitBackendKind::Native, GitBackendKind::Shell] {
+            let temp = TempDir::new(&format!("review-rename-{:?}", kind));
+            renamed(temp_repo(&temp), kind);
+        }
+    }
+
+    fn renamed(repo: PathBuf, kind: GitBackendKind) {
+        run_git(&repo, &["mv", "b.txt", "c.txt"]);
+        fs::write(repo.join("c.txt"), "three\nfour\n").unwrap();
+        let mut review = Review::new(repo.clone(), kind.backend());
+        run_jobs(&mut review);
+        let c = file(&review, "c.txt");
+        assert_eq!(c.old_path.as_deref(), Some("b.txt"));
+        assert!(c.staged && c.unstaged);
+
+        // staging adds the unstaged change, the rename stays staged
+        review.toggle_stage();
+        run_jobs(&mut review);
+        let c = file(&review, "c.txt");
+        assert!(c.staged && !c.unstaged);
+        assert_eq!(
+            run_git(
+                &repo,
+                &["diff", "--cached", "--no-renames", "--name-status"]
+            ),
+            "D\tb.txt\nA\tc.txt\n"
+        );
+
+        // unstaging removes the deletion of the original path from the index as well
+        review.toggle_stage();
+        run_jobs(&mut review);
+        assert_eq!(run_git(&repo, &["diff", "--cached"]), "");
+        assert!(!review.has_staged_files());
+    }
+
+    #[test]
+    fn only_staged_changes_are_committed() {
+        let temp = TempDir::new("review-commit");
+        let repo = temp_repo(&temp);
+        fs::write(repo.join("a.txt"), "one\nchanged\n").unwrap();
+        fs::write(repo.join("b.txt"), "not staged\n").unwrap();
+        let mut review = Review::new(repo.clone(), GitBackendKind::default().backend());
+        run_jobs(&mut review);
+        review.selected = review.files.iter().position(|f| f.path == "a.txt").unwrap();
+        review.toggle_stage();
+        run_jobs(&mut review);
+        assert!(review.has_staged_files());
+
+        let message = "Fix \"a\" for $USER\n\nIt's `done`";
+        for c in message.chars() {
+            match c {
+                '\n' => review.message.newline(),
+                c => review.message.insert_char(c),
+            }
+        }
+        let steps = review.commit_steps();
+        let status = isolate(Command::new("sh").args(["-c", &steps[0].run]))
+            .current_dir(&repo)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+        assert_eq!(
+            run_git(&repo, &["log", "-1", "--format=%B"]).trim_end(),
+            message
+        );
+        assert_eq!(
+            run_git(&repo, &["show", "--name-only", "--format=", "HEAD"]),
+            "a.txt\n"
+        );
+
+        review.refresh();
+        run_jobs(&mut review);
+        assert_eq!(review.files.len(), 1);
+        assert_eq!(review.files[0].path, "b.txt");
+        assert!(!review.has_staged_files());
+    }
+}
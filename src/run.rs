@@ -0,0 +1,269 @@
+use zeroize::Zeroize;
+
+use crate::{
+    functions::{InputMessage, OutputMessage, Stream, TaskSummary},
+    output::OutputBuffer,
+    secret::{pop_wiped, push_wiped, Secret},
+};
+
+/// Line below the output that is sent to stdin of the running task. The typed text may be a
+/// password, it is wiped from memory when it is sent, removed or the line is dropped.
+#[derive(Default)]
+pub struct InputLine {
+    pub buffer: String,
+    pub masked: bool,        // the input is a password and shown as *
+    pub active: bool,        // the task is running and accepts input
+    pub sudo_password: bool, // the input is the password that sudo asked for, it is cached
+    pub literal_next: bool,  // Ctrl+V was pressed, the next Ctrl key is sent to the task
+}
+
+// Ctrl keys that the app handles while the input line has the focus. They are only sent to the
+// task right after Ctrl+V.
+const RESERVED_CONTROL_KEYS: [char; 6] = ['c', 'f', 'q', 's', 'v', 'w'];
+
+impl InputLine {
+    pub fn push(&mut self, c: char) {
+        push_wiped(&mut self.buffer, c);
+    }
+
+    pub fn pop(&mut self) {
+        pop_wiped(&mut self.buffer);
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.zeroize();
+    }
+
+    /// Remove the typed text, followed by the byte (newline, control character), to send it. A
+    /// masked input is sent as a secret. The next input is not masked anymore.
+    pub fn take(&mut self, end: Option<u8>) -> InputMessage {
+        let mut bytes = Vec::with_capacity(self.buffer.len() + 1);
+        bytes.extend_from_slice(self.buffer.as_bytes());
+        bytes.extend(end);
+        self.buffer.zeroize();
+        let masked = std::mem::take(&mut self.masked);
+        self.sudo_password = false;
+        if masked {
+            InputMessage::Secret(Secret::new(bytes))
+        } else {
+            InputMessage::Bytes(bytes)
+        }
+    }
+
+    /// Input for Ctrl and the letter, the typed text is sent before it. A key of the app is only
+    /// sent right after Ctrl+V, and Ctrl+V makes the next key a literal one. Ctrl+Z is never sent,
+    /// it would stop the command without a way to continue it. None if nothing is sent.
+    pub fn control_key(&mut self, letter: char) -> Option<InputMessage> {
+        let literal = std::mem::take(&mut self.literal_next);
+        if letter == 'z' {
+            return None;
+        }
+        if !literal && RESERVED_CONTROL_KEYS.contains(&letter) {
+            self.literal_next = letter == 'v';
+            return None;
+        }
+        control_byte(letter).map(|byte| self.take(Some(byte)))
+    }
+
+    /// Copy of the typed text that is kept after it was sent, like the cached sudo password
+    pub fn secret(&self) -> Secret {
+        Secret::from(self.buffer.as_str())
+    }
+}
+
+impl Drop for InputLine {
+    fn drop(&mut self) {
+        self.buffer.zeroize();
+    }
+}
+
+/// Byte that a terminal sends for Ctrl and the letter, e.g. 0x01 for Ctrl+A
+fn control_byte(letter: char) -> Option<u8> {
+    letter
+        .is_ascii_alphabetic()
+        .then(|| letter.to_ascii_lowercase() as u8 - b'a' + 1)
+}
+
+/// Output, progress and input of the task that runs or ran last. The run is kept apart from the
+/// menu, so the menu stays intact while the output is shown next to it.
+pub struct TaskRun {
+    pub name: String,
+    pub output: OutputBuffer,
+    pub step_progress: Option<(usize, usize, String)>, // index, number and name of the running step
+    pub summary: Option<TaskSummary>,                  // result of the finished task
+    pub input: InputLine,
+    // sudo waits for the password, true if the last one was wrong
+    pub password_request: Option<bool>,
+}
+
+impl TaskRun {
+    pub fn new(name: String, scrollback: usize) -> TaskRun {
+        TaskRun {
+            name,
+            output: OutputBuffer::new(scrollback),
+            step_progress: None,
+            summary: None,
+            input: InputLine::default(),
+            password_request: None,
+        }
+    }
+
+    /// Update the output and the state of the task with a message of the running task
+    pub fn handle_output(&mut self, msg: OutputMessage) {
+        match msg {
+            OutputMessage::Line(stream, line) => self.output.push(stream, &line, false),
+            OutputMessage::Partial(stream, line) => {
+                // prompts like the one of doas are answered in the masked input line
+                if line.trim_end().to_lowercase().ends_with("password:") {
+                    self.input.masked = true;
+                }
+                self.output.push(stream, &line, true)
+            }
+            OutputMessage::PasswordRequested { retry } => self.password_request = Some(retry),
+            OutputMessage::StepStarted { index, total, name } => {
+                self.output.push(
+                    Stream::Info,
+                    &format!("Step {}/{}: {}", index + 1, total, name),
+                    false,
+                );
+                self.step_progress = Some((index, total, name));
+            }
+            OutputMessage::StepFinished { index, completion } => {
+                let total = self
+                    .step_progress
+                    .as_ref()
+                    .map_or(0, |(_, total, _)| *total);
+                self.output.push(
+                    Stream::Info,
+                    &format!(
+                        "Step {}/{} finished ({}) after {:.2}s",
+                        index + 1,
+                        total,
+                        completion.reason(),
+                        completion.duration.as_secs_f64()
+                    ),
+                    false,
+                );
+            }
+            OutputMessage::Finished(summary) => {
+                self.output.push(Stream::Info, "Summary:", false);
+                for (i, step) in summary.steps.iter().enumerate() {
+                    let result = match &step.completion {
+                        Some(completion) if completion.success() => "ok".to_string(),
+                        Some(completion) if step.continue_on_error => {
+                            format!("failed, ignored ({})", completion.reason())
+                        }
+                        Some(completion) => format!("failed ({})", completion.reason()),
+                        None => "skipped".to_string(),
+                    };
+                    self.output.push(
+                        Stream::Info,
+                        &format!("  {}. {}: {}", i + 1, step.name, result),
+                        false,
+                    );
+                }
+                self.step_progress = None;
+                self.password_request = None;
+                self.summary = Some(summary);
+                self.input = InputLine::default();
+            }
+        }
+    }
+
+    /// Ask the user for the sudo password in the masked input line
+    pub fn ask_password(&mut self, retry: bool) {
+        let message = if retry {
+            "Wrong password, enter the password for sudo again"
+        } else {
+            "Enter the password for sudo"
+        };
+        self.output.push(Stream::Info, message, false);
+        self.input.clear();
+        self.input.active = true;
+        self.input.masked = true;
+        self.input.sudo_password = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes of the whole allocation of the buffer, including the unused capacity
+    fn allocation(buffer: &String) -> Vec<u8> {
+        // SAFETY: only called after zeroize, which writes every byte of the capacity
+        unsafe { std::slice::from_raw_parts(buffer.as_ptr(), buffer.capacity()) }.to_vec()
+    }
+
+    fn typed(text: &str, masked: bool) -> InputLine {
+        let mut input = InputLine::default();
+        input.masked = masked;
+        input.active = true;
+        text.chars().for_each(|c| input.push(c));
+        input
+    }
+
+    #[test]
+    fn masked_input_is_sent_as_secret_and_cleared() {
+        let mut input = typed("hunter2", true);
+        let message = input.take(Some(b'\n'));
+        assert_eq!(message, InputMessage::Secret(Secret::from("hunter2\n")));
+        assert!(input.buffer.is_empty());
+        assert!(input.buffer.capacity() > 0);
+        assert!(allocation(&input.buffer).iter().all(|&byte| byte == 0));
+        assert!(!input.masked);
+    }
+
+    #[test]
+    fn plain_input_is_sent_as_bytes() {
+        let mut input = typed("ls", false);
+        assert_eq!(input.take(None), InputMessage::Bytes(b"ls".to_vec()));
+        assert!(input.buffer.is_empty());
+    }
+
+    #[test]
+    fn editing_keeps_the_text() {
+        let long: String = "pässwörd".repeat(10);
+        let mut input = typed(&long, true);
+        assert_eq!(input.buffer, long);
+        input.pop();
+        input.pop();
+        assert_eq!(input.buffer, long[..long.len() - "rd".len()]);
+    }
+
+    #[test]
+    fn asking_for_the_password_clears_typed_text() {
+        let mut run = TaskRun::new("Root".to_string(), 100);
+        "typed before".chars().for_each(|c| run.input.push(c));
+        run.ask_password(false);
+        assert!(run.input.buffer.is_empty());
+        assert!(allocation(&run.input.buffer).iter().all(|&byte| byte == 0));
+        assert!(run.input.masked && run.input.sudo_password);
+    }
+
+    #[test]
+    fn keys_of_the_app_are_sent_after_ctrl_v() {
+        let mut input = typed("ab", false);
+        assert_eq!(input.control_key('s'), None);
+        assert_eq!(
+            input.control_key('a'),
+            Some(InputMessage::Bytes(b"ab\x01".to_vec()))
+        );
+        assert_eq!(input.control_key('v'), None);
+        assert!(input.literal_next);
+        assert_eq!(
+            input.control_key('s'),
+            Some(InputMessage::Bytes(vec![0x13]))
+        );
+        // only the next key is literal
+        assert_eq!(input.control_key('w'), None);
+        input.control_key('v');
+        assert_eq!(
+            input.control_key('v'),
+            Some(InputMessage::Bytes(vec![0x16]))
+        );
+        input.control_key('v');
+        assert_eq!(input.control_key('z'), None);
+        assert!(!input.literal_next);
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+
+use zeroize::{Zeroize, Zeroizing};
+
+/// Input that must not stay in memory, like a password typed into the masked input line. The bytes
+/// are overwritten with zeros when the secret is dropped and are never shown by Debug.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl Secret {
+    pub fn new(bytes: Vec<u8>) -> Secret {
+        Secret(Zeroizing::new(bytes))
+    }
+
+    pub fn into_bytes(self) -> Zeroizing<Vec<u8>> {
+        self.0
+    }
+
+    /// Copy of the secret followed by the byte, e.g. the newline that ends a password. The copy is
+    /// allocated with its final size, growing it would leave the old bytes behind.
+    pub fn with_byte(&self, byte: u8) -> Secret {
+        let mut bytes = Vec::with_capacity(self.0.len() + 1);
+        bytes.extend_from_slice(&self.0);
+        bytes.push(byte);
+        Secret::new(bytes)
+    }
+}
+
+/// Append the character to text that may be a password. The string is grown by hand, the
+/// reallocation of push would leave a copy of the text behind.
+pub fn push_wiped(text: &mut String, c: char) {
+    if text.len() + c.len_utf8() > text.capacity() {
+        let mut grown = String::with_capacity((text.capacity() * 2).max(32));
+        grown.push_str(text);
+        text.zeroize();
+        *text = grown;
+    }
+    text.push(c);
+}
+
+/// Remove the last character of text that may be a password. pop would leave the removed
+/// character in the unused capacity.
+pub fn pop_wiped(text: &mut String) {
+    let mut shorter = String::with_capacity(text.capacity());
+    let mut chars = text.chars();
+    chars.next_back();
+    shorter.push_str(chars.as_str());
+    text.zeroize();
+    *text = shorter;
+}
+
+impl From<&str> for Secret {
+    fn from(text: &str) -> Secret {
+        Secret::new(text.as_bytes().to_vec())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_hides_the_secret() {
+        let secret = Secret::from("hunter2");
+        let debug = format!("{:?}", secret);
+        assert_eq!(debug, "Secret(***)");
+        assert!(!format!("{:?}", Some(secret.with_byte(b'\n'))).contains("hunter2"));
+    }
+
+    #[test]
+    fn with_byte_appends_to_a_copy() {
+        let secret = Secret::from("hunter2");
+        let line = secret.with_byte(b'\n');
+        assert_eq!(line.into_bytes().as_slice(), b"hunter2\n");
+        assert_eq!(secret.into_bytes().as_slice(), b"hunter2");
+    }
+}
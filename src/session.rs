@@ -0,0 +1,296 @@
+use std::{collections::VecDeque, time::Instant};
+
+use tokio::sync::mpsc::{
+    self,
+    error::{TryRecvError, TrySendError},
+    Receiver, Sender,
+};
+
+use crate::{
+    functions::{InputMessage, OutputMessage, RunOptions, TaskHandle},
+    run::{InputLine, TaskRun},
+    tasks::Step,
+};
+
+/// State of a session as shown in the tab bar and the session list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Running,
+    Finished, // all steps succeeded
+    Failed,   // a step failed, the task was cancelled or could not be started
+}
+
+/// A launched task with its own output, stdin channel and process. Sessions run independently of
+/// each other, so their output does not interleave and input only reaches the selected task.
+pub struct Session {
+    pub run: TaskRun,
+    pub started: Instant,
+    task: TaskHandle,
+    rx_output: Receiver<OutputMessage>, // stdout/stderr of the task
+    tx_input: Sender<InputMessage>,     // input that is written to stdin of the running command
+    // input that did not fit into the channel yet, the task only reads it while a command runs
+    pending: VecDeque<InputMessage>,
+    closed: bool, // the task ended and all of its output is received
+}
+
+impl Session {
+    /// Run the steps in the background. Everything that is typed is sent to stdin of the task.
+    pub fn spawn(
+        name: String,
+        steps: Vec<Step>,
+        options: RunOptions,
+        scrollback: usize,
+    ) -> Session {
+        let (tx_output, rx_output) = mpsc::channel::<OutputMessage>(5);
+        let (tx_input, rx_input) = mpsc::channel::<InputMessage>(5);
+        let task = TaskHandle::spawn(tx_output, rx_input, steps, options);
+        let mut run = TaskRun::new(name, scrollback);
+        run.input.active = true;
+        Session {
+            run,
+            started: Instant::now(),
+            task,
+            rx_output,
+            tx_input,
+            pending: VecDeque::new(),
+            closed: false,
+        }
+    }
+
+    /// Read the output that the task sent since the last call and pass on the pending input
+    pub fn receive(&mut self) {
+        self.flush_input();
+        loop {
+            match self.rx_output.try_recv() {
+                Ok(msg) => self.run.handle_output(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // the task ended without a summary if it could not be started
+                    self.closed = true;
+                    self.run.input = InputLine::default();
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn state(&self) -> SessionState {
+        match &self.run.summary {
+            Some(summary) if summary.success() => SessionState::Finished,
+            Some(_) => SessionState::Failed,
+            None if self.closed => SessionState::Failed,
+            None => SessionState::Running,
+        }
+    }
+
+    /// Send input to stdin of the task. The input is queued and never blocks the UI, e.g. while
+    /// the task waits for a retry. Input for a task that already ended is dropped.
+    pub fn send_input(&mut self, input: InputMessage) {
+        self.pending.push_back(input);
+        self.flush_input();
+    }
+
+    /// Move the pending input into the channel as far as it has room
+    fn flush_input(&mut self) {
+        while let Some(input) = self.pending.pop_front() {
+            match self.tx_input.try_send(input) {
+                Ok(()) => {}
+                Err(TrySendError::Full(input)) => {
+                    self.pending.push_front(input);
+                    break;
+                }
+                Err(TrySendError::Closed(_)) => {
+                    self.pending.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.task.cancel();
+    }
+}
+
+/// All sessions that were launched and not closed yet, in the order of the tab bar
+#[derive(Default)]
+pub struct Sessions {
+    pub sessions: Vec<Session>,
+    pub selected: usize, // the session whose output and input are shown
+}
+
+impl Sessions {
+    /// Add the session and select it
+    pub fn push(&mut self, session: Session) {
+        self.sessions.push(session);
+        self.selected = self.sessions.len() - 1;
+    }
+
+    pub fn active(&self) -> Option<&Session> {
+        self.sessions.get(self.selected)
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Session> {
+        self.sessions.get_mut(self.selected)
+    }
+
+    pub fn next(&mut self) {
+        if !self.sessions.is_empty() {
+            self.selected = (self.selected + 1) % self.sessions.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.sessions.is_empty() {
+            self.selected = (self.selected + self.sessions.len() - 1) % self.sessions.len();
+        }
+    }
+
+    /// Read the output of all sessions
+    pub fn receive(&mut self) {
+        for session in &mut self.sessions {
+            session.receive();
+        }
+    }
+
+    /// Remove the selected session. A session whose task is still running is kept, returns
+    /// whether the session was closed.
+    pub fn close_selected(&mut self) -> bool {
+        match self.active() {
+            Some(session) if session.state() != SessionState::Running => {
+                self.sessions.remove(self.selected);
+                self.selected = self.selected.min(self.sessions.len().saturating_sub(1));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancel the tasks of all sessions and wait until all of their processes are terminated
+    pub async fn shutdown(&mut self) {
+        for session in &self.sessions {
+            session.cancel();
+        }
+        for session in self.sessions.drain(..) {
+            session.task.shutdown().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::functions::Stream;
+
+    fn session(name: &str, run: &str) -> Session {
+        let step = Step {
+            run: run.to_string(),
+            name: None,
+            continue_on_error: false,
+        };
+        Session::spawn(name.to_string(), vec![step], RunOptions::default(), 100)
+    }
+
+    /// Receive the output until the session is no longer running
+    async fn wait(session: &mut Session) {
+        for _ in 0..500 {
+            session.receive();
+            if session.state() != SessionState::Running {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{} did not finish", session.run.name);
+    }
+
+    /// Lines that the task wrote to stdout
+    fn output(session: &Session) -> Vec<String> {
+        let lines = session.run.output.lines.iter();
+        lines
+            .filter(|(stream, _)| *stream == Stream::Stdout)
+            .map(|(_, line)| line.to_string())
+            .collect()
+    }
+
+    fn names(sessions: &Sessions) -> Vec<&str> {
+        let sessions = sessions.sessions.iter();
+        sessions.map(|session| session.run.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn state_follows_the_result_of_the_task() {
+        let mut ok = session("ok", "echo done");
+        let mut failed = session("failed", "exit 3");
+        assert_eq!(ok.state(), SessionState::Running);
+        wait(&mut ok).await;
+        wait(&mut failed).await;
+        assert_eq!(ok.state(), SessionState::Finished);
+        assert_eq!(failed.state(), SessionState::Failed);
+        assert!(output(&ok).iter().any(|line| line == "done"));
+    }
+
+    #[tokio::test]
+    async fn input_only_reaches_its_session() {
+        let mut first = session("first", "read line; echo first $line");
+        let mut second = session("second", "read line; echo second $line");
+        second.send_input(InputMessage::Bytes(b"two\n".to_vec()));
+        first.send_input(InputMessage::Bytes(b"one\n".to_vec()));
+        wait(&mut first).await;
+        wait(&mut second).await;
+        assert!(output(&first).iter().any(|line| line == "first one"));
+        assert!(output(&second).iter().any(|line| line == "second two"));
+        assert!(!output(&first).iter().any(|line| line.contains("two")));
+    }
+
+    #[tokio::test]
+    async fn sessions_are_selected_in_tab_order() {
+        let mut sessions = Sessions::default();
+        for name in ["a", "b", "c"] {
+            sessions.push(session(name, "true"));
+        }
+        assert_eq!(names(&sessions), ["a", "b", "c"]);
+        // a new session is selected
+        assert_eq!(sessions.selected, 2);
+        sessions.next();
+        assert_eq!(sessions.active().unwrap().run.name, "a");
+        sessions.previous();
+        sessions.previous();
+        assert_eq!(sessions.active().unwrap().run.name, "b");
+        sessions.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn only_finished_sessions_are_closed() {
+        let mut sessions = Sessions::default();
+        sessions.push(session("done", "true"));
+        sessions.push(session("running", "sleep 30"));
+        assert!(!sessions.close_selected());
+        assert_eq!(names(&sessions), ["done", "running"]);
+
+        sessions.previous();
+        wait(sessions.active_mut().unwrap()).await;
+        assert!(sessions.close_selected());
+        assert_eq!(names(&sessions), ["running"]);
+        assert_eq!(sessions.selected, 0);
+        sessions.shutdown().await;
+        assert!(sessions.sessions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn queued_input_keeps_its_order() {
+        let mut session = session("cat", "sleep 0.2; cat");
+        for i in 0..20 {
+            session.send_input(InputMessage::Bytes(format!("{}\n", i).into_bytes()));
+        }
+        session.send_input(InputMessage::Eof);
+        // the channel is full, the rest waits without blocking
+        assert!(!session.pending.is_empty());
+        wait(&mut session).await;
+        assert!(session.pending.is_empty());
+        let expected: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        assert_eq!(output(&session), expected);
+        assert_eq!(session.state(), SessionState::Finished);
+    }
+}
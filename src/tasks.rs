@@ -0,0 +1,600 @@
+use std::{path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::functions::{expand_home, Completion, RunOptions};
+
+/// A single entry of the task catalog. Every task is a list of shell steps that are executed one
+/// after another by `functions::run_task` when the task is selected in the start screen.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Task {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    // built-in screen that is opened instead of running steps
+    #[serde(default)]
+    pub workflow: Option<Workflow>,
+    // values that are entered by the user in a form before the steps are run. `{{name}}` in the
+    // commands of the steps is replaced with the environment variable `LINUTIL_PARAM_NAME`.
+    #[serde(default)]
+    pub params: Vec<Param>,
+    // directory the steps are run in, may contain parameters like `{{git_repo_dir}}`
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    // run the steps inside a pseudo-terminal. Necessary for programs that only prompt on a tty.
+    #[serde(default)]
+    pub pty: bool,
+    // seconds after which the process group of a step is killed
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    // the steps are run as root with sudo, doas or pkexec
+    #[serde(default)]
+    pub requires_root: bool,
+}
+
+/// Built-in screens that can be opened by a task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Workflow {
+    // review, commit and push the changes of the repository in `working_dir`
+    Push,
+}
+
+impl Task {
+    /// Settings to run the steps. Parameters in the working directory are substituted without
+    /// quoting. The values of all parameters are passed to the steps as environment variables.
+    pub fn run_options(&self, values: &[(String, Zeroizing<String>)]) -> RunOptions {
+        let working_dir = self.working_dir.as_ref().map(|dir| {
+            let dir = substitute(dir, |name, _| lookup(values, name).map(str::to_string));
+            PathBuf::from(expand_home(&dir))
+        });
+        let envs = values
+            .iter()
+            .map(|(name, value)| (param_env_var(name), value.clone()))
+            .collect();
+        let secret_envs = values
+            .iter()
+            .filter(|(name, _)| self.is_password(name))
+            .map(|(name, _)| param_env_var(name))
+            .collect();
+        RunOptions {
+            pty: self.pty,
+            working_dir,
+            timeout: self.timeout.map(Duration::from_secs),
+            retry: self.retry.clone(),
+            envs,
+            secret_envs,
+            ..RunOptions::default()
+        }
+    }
+
+    /// Steps of the task with every parameter replaced by a reference to its environment
+    /// variable. bash expands the reference to a single word without parsing the value, so no
+    /// value can inject commands. An empty optional value outside of quotes is left out instead of
+    /// passing an empty argument. The values neither show up in the step names nor in `ps`,
+    /// except for tasks that doas or pkexec run as root (see `Escalation::args`).
+    pub fn steps_with_params(&self, values: &[(String, Zeroizing<String>)]) -> Vec<Step> {
+        let replacement = |name: &str, quote: Quote| {
+            lookup(values, name)?;
+            let var = param_env_var(name);
+            Some(match quote {
+                Quote::None => format!("${{{0}:+\"${0}\"}}", var),
+                Quote::Double => format!("${{{}}}", var),
+                // the single quotes are closed around the reference
+                Quote::Single => format!("'\"${{{}}}\"'", var),
+            })
+        };
+        self.steps
+            .iter()
+            .map(|step| Step {
+                run: substitute(&step.run, replacement),
+                ..step.clone()
+            })
+            .collect()
+    }
+
+    fn is_password(&self, name: &str) -> bool {
+        self.params
+            .iter()
+            .any(|param| param.name == name && matches!(param.kind, ParamKind::Password))
+    }
+}
+
+/// Name and value of every parameter of a task. The values may be passwords, they are wiped from
+/// memory when they are dropped.
+pub type ParamValues = Vec<(String, Zeroizing<String>)>;
+
+/// Value of the parameter, the first one wins if the name is used twice
+fn lookup<'a>(values: &'a [(String, Zeroizing<String>)], name: &str) -> Option<&'a str> {
+    values
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Quotes of bash that a placeholder is inside of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+impl Quote {
+    /// Quotes after the text, when the text starts inside of `self`
+    fn after(self, text: &str) -> Quote {
+        let mut quote = self;
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            quote = match (quote, c) {
+                (Quote::None | Quote::Double, '\\') => {
+                    chars.next();
+                    quote
+                }
+                (Quote::None, '\'') => Quote::Single,
+                (Quote::None, '"') => Quote::Double,
+                (Quote::Single, '\'') | (Quote::Double, '"') => Quote::None,
+                _ => quote,
+            };
+        }
+        quote
+    }
+}
+
+/// Replace every `{{name}}` of the template with the replacement for the name and the quotes the
+/// placeholder is inside of. The template is scanned once, so placeholders inside of inserted
+/// values are never expanded. Placeholders without a replacement are kept.
+fn substitute(template: &str, replacement: impl Fn(&str, Quote) -> Option<String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut quote = Quote::None;
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        quote = quote.after(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after
+            .find("}}")
+            .and_then(|end| Some((end, replacement(&after[..end], quote)?)));
+        match value {
+            Some((end, value)) => {
+                result.push_str(&value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                result.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Environment variable that holds the value of a parameter
+pub fn param_env_var(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("LINUTIL_PARAM_{}", name)
+}
+
+/// A parameter of a task that is entered in the form before the task is run
+#[derive(Debug, Clone, Deserialize)]
+pub struct Param {
+    pub name: String,
+    // text that is shown in the form instead of the name
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub kind: ParamKind,
+    #[serde(default)]
+    pub default: Option<toml::Value>,
+    // an empty value is accepted
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl Param {
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Default value as text, booleans default to false and choices to the first entry
+    pub fn default_value(&self) -> String {
+        match (&self.default, &self.kind) {
+            (Some(toml::Value::String(value)), _) => value.clone(),
+            (Some(value), _) => value.to_string(),
+            (None, ParamKind::Boolean) => "false".to_string(),
+            (None, ParamKind::Choice { choices }) => choices.first().cloned().unwrap_or_default(),
+            (None, _) => String::new(),
+        }
+    }
+
+    /// Check the entered value. Returns the value that is substituted into the steps.
+    pub fn validate(&self, value: &str) -> Result<String, String> {
+        if value.is_empty() {
+            return if self.optional || matches!(self.kind, ParamKind::Boolean) {
+                Ok(String::new())
+            } else {
+                Err("a value is required".to_string())
+            };
+        }
+        match &self.kind {
+            ParamKind::String | ParamKind::Password => Ok(value.to_string()),
+            ParamKind::Boolean => match value {
+                "true" | "false" => Ok(value.to_string()),
+                _ => Err("must be true or false".to_string()),
+            },
+            ParamKind::Choice { choices } => {
+                if choices.iter().any(|choice| choice == value) {
+                    Ok(value.to_string())
+                } else {
+                    Err(format!("must be one of: {}", choices.join(", ")))
+                }
+            }
+            ParamKind::Path { must_exist } => {
+                let path = expand_home(value);
+                if *must_exist && !std::path::Path::new(&path).exists() {
+                    Err(format!("{} does not exist", path))
+                } else {
+                    Ok(path)
+                }
+            }
+            ParamKind::Integer { min, max } => {
+                let number: i64 = value
+                    .parse()
+                    .map_err(|_| "must be a whole number".to_string())?;
+                match (min, max) {
+                    (Some(min), _) if number < *min => Err(format!("must be at least {}", min)),
+                    (_, Some(max)) if number > *max => Err(format!("must be at most {}", max)),
+                    _ => Ok(number.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Type of a parameter, set with `type = "..."` in the catalog
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ParamKind {
+    String,
+    // input is masked in the form
+    Password,
+    Boolean,
+    Choice {
+        choices: Vec<String>,
+    },
+    Path {
+        #[serde(default)]
+        must_exist: bool,
+    },
+    Integer {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+}
+
+/// Quote a value for bash. Single quotes prevent every expansion, a single quote in the value is
+/// closed, escaped and opened again.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// A step of a task. Every step is run in its own bash process. In the catalog a step is either
+/// the command itself or a table with `run`, `name` and `continue_on_error`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "StepDefinition")]
+pub struct Step {
+    pub run: String,
+    pub name: Option<String>,
+    // a failure of this step does not stop the task
+    pub continue_on_error: bool,
+}
+
+impl Step {
+    /// Name of the step that is shown in the UI, the command if no name is set
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.run)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StepDefinition {
+    Command(String),
+    Table {
+        run: String,
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        continue_on_error: bool,
+    },
+}
+
+impl From<StepDefinition> for Step {
+    fn from(definition: StepDefinition) -> Self {
+        match definition {
+            StepDefinition::Command(run) => Step {
+                run,
+                name: None,
+                continue_on_error: false,
+            },
+            StepDefinition::Table {
+                run,
+                name,
+                continue_on_error,
+            } => Step {
+                run,
+                name,
+                continue_on_error,
+            },
+        }
+    }
+}
+
+// longest time that is waited before a retry, however large the backoff factor is
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Defines if and how often a failed step is run again
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetryPolicy {
+    // number of attempts including the first one
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    // seconds to wait before the first retry
+    #[serde(default)]
+    pub backoff: u64,
+    // the waiting time is multiplied with this factor after every retry
+    #[serde(default = "default_backoff_factor")]
+    pub backoff_factor: u32,
+    // only retry if the task failed with one of these exit codes. If empty, every failure is
+    // retried. Timeouts are always retried.
+    #[serde(default)]
+    pub exit_codes: Vec<i32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: default_max_attempts(),
+            backoff: 0,
+            backoff_factor: default_backoff_factor(),
+            exit_codes: Vec::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Time to wait before the next attempt after the failed attempt (starting at 1). It grows by
+    /// the backoff factor with every retry, capped at an hour so that it can not overflow.
+    pub fn backoff_after(&self, attempt: u32) -> Duration {
+        let factor = self
+            .backoff_factor
+            .saturating_pow(attempt.saturating_sub(1));
+        Duration::from_secs(self.backoff)
+            .checked_mul(factor)
+            .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF))
+    }
+
+    pub fn should_retry(&self, completion: &Completion) -> bool {
+        if completion.timed_out || self.exit_codes.is_empty() {
+            return true;
+        }
+        completion
+            .code
+            .is_some_and(|code| self.exit_codes.contains(&code))
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    1
+}
+
+fn default_backoff_factor() -> u32 {
+    2
+}
+
+/// The task catalog as it is read from the toml file. Tasks are declared as `[[task]]` tables.
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskCatalog {
+    #[serde(default, rename = "task")]
+    pub tasks: Vec<Task>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let retry = RetryPolicy {
+            max_attempts: 100,
+            backoff: 2,
+            backoff_factor: 3,
+            exit_codes: Vec::new(),
+        };
+        let delays: Vec<u64> = (1..=4)
+            .map(|attempt| retry.backoff_after(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, [2, 6, 18, 54]);
+        assert_eq!(retry.backoff_after(99), MAX_BACKOFF);
+
+        let huge = RetryPolicy {
+            backoff: u64::MAX,
+            backoff_factor: u32::MAX,
+            ..retry
+        };
+        assert_eq!(huge.backoff_after(1), MAX_BACKOFF);
+        assert_eq!(huge.backoff_after(u32::MAX), MAX_BACKOFF);
+    }
+
+    fn task(toml: &str) -> Task {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> ParamValues {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), Zeroizing::new(value.to_string())))
+            .collect()
+    }
+
+    /// Output of the first step of the task with the values, run by bash like a task
+    fn run_first_step(task: &Task, values: &ParamValues) -> String {
+        let steps = task.steps_with_params(values);
+        let options = task.run_options(values);
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(&steps[0].run)
+            .envs(options.envs.iter().map(|(n, v)| (n.as_str(), v.as_str())))
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Words that bash makes of the first step, printed one per line
+    fn words(task: &Task, values: &ParamValues) -> Vec<String> {
+        let task = Task {
+            steps: vec![Step {
+                run: format!("printf '%s\\n' {}", task.steps[0].run),
+                ..task.steps[0].clone()
+            }],
+            ..task.clone()
+        };
+        run_first_step(&task, values)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    const ECHO: &str = r#"
+        name = "Echo"
+        steps = ["{{a}} {{b}}"]
+        params = [{ name = "a", type = "string" }, { name = "b", type = "string" }]
+    "#;
+
+    #[test]
+    fn placeholders_are_replaced_with_env_vars() {
+        let values = values(&[("a", "x; echo INJECTED"), ("b", "y")]);
+        let steps = task(ECHO).steps_with_params(&values);
+        assert_eq!(
+            steps[0].run,
+            "${LINUTIL_PARAM_A:+\"$LINUTIL_PARAM_A\"} ${LINUTIL_PARAM_B:+\"$LINUTIL_PARAM_B\"}"
+        );
+        let options = task(ECHO).run_options(&values);
+        assert_eq!(options.envs[0].0, "LINUTIL_PARAM_A");
+        assert_eq!(options.envs[0].1.as_str(), "x; echo INJECTED");
+        assert!(options.secret_envs.is_empty());
+    }
+
+    #[test]
+    fn placeholders_in_values_are_not_expanded() {
+        let values = values(&[("a", "{{b}}"), ("b", "x; echo INJECTED")]);
+        assert_eq!(words(&task(ECHO), &values), ["{{b}}", "x; echo INJECTED"]);
+    }
+
+    #[test]
+    fn other_params_are_not_expanded_in_values() {
+        let mut values = values(&[("a", "{{name}}"), ("b", "ok")]);
+        values.push((
+            "name".to_string(),
+            Zeroizing::new("'; echo INJECTED; '".to_string()),
+        ));
+        assert_eq!(words(&task(ECHO), &values), ["{{name}}", "ok"]);
+    }
+
+    #[test]
+    fn quotes_and_separators_stay_in_one_word() {
+        for value in [
+            "it's",
+            "a'b\"c",
+            "$(echo INJECTED)",
+            "`id`",
+            "x && y | z",
+            "\\'",
+            "''",
+            "*",
+        ] {
+            let values = values(&[("a", value), ("b", "end")]);
+            assert_eq!(
+                words(&task(ECHO), &values),
+                [value, "end"],
+                "value {:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn values_inside_of_quotes_are_not_expanded() {
+        let task = task(
+            r#"
+            name = "Greet"
+            steps = ["echo \"Hi {{a}}!\" 'and {{a}}' \"\\\"{{a}}\\\"\""]
+            params = [{ name = "a", type = "string" }]
+        "#,
+        );
+        let values = values(&[("a", "$(echo INJECTED) `id`")]);
+        assert_eq!(
+            run_first_step(&task, &values),
+            "Hi $(echo INJECTED) `id`! and $(echo INJECTED) `id` \"$(echo INJECTED) `id`\"\n"
+        );
+    }
+
+    #[test]
+    fn unknown_and_unclosed_placeholders_are_kept() {
+        assert_eq!(substitute("{{x}} {{a", |_, _| None), "{{x}} {{a");
+        assert_eq!(
+            substitute("{{{a}}}", |name, _| (name == "a").then(|| "1".to_string())),
+            "{{{a}}}"
+        );
+        assert_eq!(substitute("{{a}}{{a}}", |_, _| Some("1".to_string())), "11");
+    }
+
+    #[test]
+    fn empty_values_are_left_out() {
+        let values = values(&[("a", ""), ("b", "x")]);
+        assert_eq!(words(&task(ECHO), &values), ["x"]);
+    }
+
+    #[test]
+    fn passwords_are_passed_in_the_environment() {
+        let task = task(
+            r#"
+            name = "Secret"
+            steps = ["echo login {{pw}} > /dev/null"]
+            params = [{ name = "pw", type = "password" }]
+        "#,
+        );
+        let values = values(&[("pw", "hunter2")]);
+        let steps = task.steps_with_params(&values);
+        assert_eq!(
+            steps[0].run,
+            "echo login ${LINUTIL_PARAM_PW:+\"$LINUTIL_PARAM_PW\"} > /dev/null"
+        );
+        assert!(!steps[0].display_name().contains("hunter2"));
+        let options = task.run_options(&values);
+        assert_eq!(options.envs.len(), 1);
+        assert_eq!(options.envs[0].0, "LINUTIL_PARAM_PW");
+        assert_eq!(options.envs[0].1.as_str(), "hunter2");
+        assert_eq!(options.secret_envs, ["LINUTIL_PARAM_PW"]);
+        assert!(!format!("{:?}", options).contains("hunter2"));
+    }
+}
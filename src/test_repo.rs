@@ -0,0 +1,62 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Temporary directory that is removed at the end of the test
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("linutil_rs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Let git ignore the config of the system and the user (hooks, templates, default branch,
+/// signing) and use a fixed identity and date
+pub fn isolate(command: &mut Command) -> &mut Command {
+    command
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .env("GIT_AUTHOR_DATE", "2020-01-01T12:00:00Z")
+        .env("GIT_COMMITTER_DATE", "2020-01-01T12:00:00Z")
+}
+
+/// Run git in the directory, independent of the config of the user. Returns stdout.
+pub fn run_git(dir: &Path, args: &[&str]) -> String {
+    let output = isolate(Command::new("git").arg("-C").arg(dir).args(args))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?}: {:?}", args, output);
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn write(dir: &Path, file: &str, content: &str) {
+    let path = dir.join(file);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+/// Create a repository in the directory with the branch main and one commit of the files
+pub fn init_repo(dir: &Path, files: &[(&str, &str)]) {
+    std::fs::create_dir_all(dir).unwrap();
+    run_git(dir, &["init", "-q", "-b", "main"]);
+    for (file, content) in files {
+        write(dir, file, content);
+    }
+    run_git(dir, &["add", "."]);
+    run_git(dir, &["commit", "-q", "-m", "initial"]);
+}
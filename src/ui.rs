@@ -1,14 +1,32 @@
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style, Stylize},
+    style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{
+        Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Table, TableState, Tabs, Wrap,
+    },
     Frame,
 };
 
-use crate::app::{self, App};
+use crate::{
+    app::{self, App, Toast},
+    bulk::{BulkRun, Outcome},
+    clone::CloneList,
+    dashboard::Dashboard,
+    forge::ForgeConfig,
+    form::Form,
+    functions::{Stream, TaskSummary},
+    menu::MenuNode,
+    output::OutputBuffer,
+    review::{Review, ReviewFocus},
+    run::TaskRun,
+    session::{SessionState, Sessions},
+    tasks::ParamKind,
+    validate::ConfigErrors,
+};
 
-pub fn ui(frame: &mut Frame, app: &App) {
+pub fn ui(frame: &mut Frame, app: &mut App) {
     // Create the layout sections.
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -23,101 +41,949 @@ pub fn ui(frame: &mut Frame, app: &App) {
         .borders(Borders::ALL)
         .style(Style::default());
 
-    let title = Paragraph::new(Text::styled("Linutil", Style::default().fg(Color::Green)))
-        .block(title_block);
+    let title = Paragraph::new(Text::styled("Linutil", Style::default().fg(Color::Green)));
 
-    // render the header
-    frame.render_widget(title, chunks[0]);
+    // render the header, the launched tasks are shown as tabs next to the title
+    let header = title_block.inner(chunks[0]);
+    frame.render_widget(title_block, chunks[0]);
+    if app.sessions.sessions.is_empty() {
+        frame.render_widget(title, header);
+    } else {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(9), Constraint::Fill(1)])
+            .split(header);
+        frame.render_widget(title, columns[0]);
+        frame.render_widget(session_tabs(&app.sessions), columns[1]);
+    }
 
     match app.current_screen {
-        app::CurrentScreen::Start => {
-            // create a list for the main section
-            let list_items: Vec<ListItem> = app
-                .items
-                .iter()
-                .enumerate()
-                .map(|(i, list_item)| {
-                    let style = if i == app.selected {
-                        Style::default()
-                            .fg(Color::Yellow)
-                            .add_modifier(Modifier::BOLD)
-                    } else {
-                        Style::default()
-                    };
-                    ListItem::new(list_item.clone()).style(style)
-                })
-                .collect();
-
-            // Create a List from all list items and highlight the currently selected one
-            let list = List::new(list_items).block(
-                Block::default()
-                    .title("Select an item")
-                    .borders(Borders::ALL),
+        app::CurrentScreen::Start | app::CurrentScreen::Input => {
+            let menu_focused = matches!(app.current_screen, app::CurrentScreen::Start);
+            match app.sessions.active_mut().map(|session| &mut session.run) {
+                // the output of the session is shown next to the menu, which stays as it was
+                Some(run) => {
+                    let columns = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+                        .split(chunks[1]);
+                    render_run(frame, columns[1], run, !menu_focused);
+                    render_menu(frame, columns[0], app, menu_focused);
+                }
+                None => render_menu(frame, chunks[1], app, true),
+            }
+        }
+        app::CurrentScreen::Form => {
+            if let Some(form) = &app.form {
+                render_form(frame, chunks[1], form);
+            }
+        }
+        app::CurrentScreen::Dashboard => {
+            render_dashboard(frame, chunks[1], &app.dashboard, &app.config.git_repo_dir);
+        }
+        app::CurrentScreen::Clone => {
+            render_clone_list(frame, chunks[1], &app.clone_list, &app.config.forge);
+        }
+        app::CurrentScreen::Bulk => {
+            if let Some(bulk) = &app.bulk {
+                render_bulk(frame, chunks[1], bulk);
+            }
+        }
+        app::CurrentScreen::Review => {
+            if let Some(review) = &app.review {
+                render_review(frame, chunks[1], review);
+            }
+        }
+        app::CurrentScreen::Sessions => render_sessions(frame, chunks[1], &app.sessions),
+    }
+
+    if let Some(input) = app.sessions.active().map(|session| &session.run.input) {
+        if input.active {
+            let text = if input.masked {
+                "*".repeat(input.buffer.chars().count())
+            } else {
+                input.buffer.clone()
+            };
+            let input_field = Line::from(vec![
+                Span::styled("> ", Style::default().fg(Color::Green)),
+                Span::raw(text),
+            ]);
+            let title = if input.sudo_password {
+                "Password for sudo"
+            } else if input.literal_next {
+                "Input (the next Ctrl key is sent to the task)"
+            } else {
+                "Input"
+            };
+            let input_line = Paragraph::new(input_field)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(title)
+                        .title_bottom("Ctrl+D: end of input  Ctrl+V: send the next Ctrl key"),
+                )
+                .style(Style::default().fg(Color::White));
+            frame.render_widget(&input_line, chunks[2]);
+        }
+    }
+
+    if let Some(toast) = app.toast.as_ref().filter(|toast| toast.is_visible()) {
+        render_toast(frame, chunks[1], toast);
+    }
+}
+
+/// Render the visible nodes of the menu tree, the selected node is highlighted
+fn render_menu(frame: &mut Frame, area: Rect, app: &App, focused: bool) {
+    let list_items: Vec<ListItem> = app
+        .visible_nodes
+        .iter()
+        .enumerate()
+        .map(|(i, visible_node)| {
+            let style = if i == app.selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let mut spans = vec![Span::raw("  ".repeat(visible_node.depth))];
+            match app.menu.node(&visible_node.path) {
+                Some(MenuNode::Category { name, expanded, .. }) => {
+                    let marker = if *expanded { "▾ " } else { "▸ " };
+                    spans.push(Span::styled(
+                        format!("{}{}/", marker, name),
+                        Style::default().fg(Color::Cyan),
+                    ));
+                }
+                Some(MenuNode::Task(task_index)) => {
+                    let task = &app.tasks[*task_index];
+                    spans.push(Span::raw(format!("  {}", task.name)));
+                    if task.requires_root {
+                        spans.push(Span::styled(" [root]", Style::default().fg(Color::Red)));
+                    }
+                    if !task.description.is_empty() {
+                        spans.push(Span::styled(
+                            format!("  {}", task.description),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                }
+                None => {}
+            }
+            ListItem::new(Line::from(spans)).style(style)
+        })
+        .collect();
+
+    // show the path of the opened category as breadcrumb in the title
+    let mut breadcrumb = vec!["Tasks"];
+    breadcrumb.extend(app.menu.breadcrumb());
+
+    let mut hint = String::from("Enter: open/run  ←/→: collapse/expand  Esc: up  g: git repos");
+    let has_sessions = !app.sessions.sessions.is_empty();
+    if has_sessions {
+        hint.push_str("  Tab: output  s: sessions");
+    }
+    let mut block = Block::default()
+        .title(breadcrumb.join(" > "))
+        .title_bottom(hint)
+        .borders(Borders::ALL);
+    // the border shows which pane receives the keys when the output is shown next to the menu
+    if focused && has_sessions {
+        block = block.border_style(Style::default().fg(Color::Yellow));
+    }
+    frame.render_widget(List::new(list_items).block(block), area);
+}
+
+/// Tabs with the number, name and state of every session, the selected session is highlighted
+fn session_tabs(sessions: &Sessions) -> Tabs<'static> {
+    let titles: Vec<Line> = sessions
+        .sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| {
+            let (_, color) = state_label(session.state());
+            Line::from(vec![
+                Span::raw(format!("{} {} ", i + 1, session.run.name)),
+                Span::styled("●", Style::default().fg(color)),
+            ])
+        })
+        .collect();
+    Tabs::new(titles)
+        .select(sessions.selected)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+}
+
+/// Label and color of the state of a session
+fn state_label(state: SessionState) -> (&'static str, Color) {
+    match state {
+        SessionState::Running => ("running", Color::Cyan),
+        SessionState::Finished => ("finished", Color::Green),
+        SessionState::Failed => ("failed", Color::Red),
+    }
+}
+
+/// Render one row per session with its state and how long the task ran
+fn render_sessions(frame: &mut Frame, area: Rect, sessions: &Sessions) {
+    let header = Row::new(vec!["#", "Task", "State", "Duration", "Steps"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let rows: Vec<Row> = sessions
+        .sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| {
+            let (label, color) = state_label(session.state());
+            let style = if i == sessions.selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let (duration, steps) = match &session.run.summary {
+                Some(summary) => (
+                    summary.duration,
+                    format!("{}/{} succeeded", summary.succeeded(), summary.steps.len()),
+                ),
+                None => (
+                    session.started.elapsed(),
+                    match &session.run.step_progress {
+                        Some((index, total, _)) => format!("step {}/{}", index + 1, total),
+                        None => String::new(),
+                    },
+                ),
+            };
+            Row::new(vec![
+                Cell::from((i + 1).to_string()),
+                Cell::from(session.run.name.clone()),
+                Cell::from(label).style(Style::default().fg(color)),
+                Cell::from(format!("{:.1}s", duration.as_secs_f64())),
+                Cell::from(steps),
+            ])
+            .style(style)
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(3),
+            Constraint::Percentage(35),
+            Constraint::Length(9),
+            Constraint::Length(10),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Sessions ({})", sessions.sessions.len()))
+            .title_bottom("↑/↓: select  Enter: show output  c: cancel  x: close  Esc: back"),
+    );
+    let mut state = TableState::default().with_selected(Some(sessions.selected));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+/// Render the output of the run, with the result below it once the task finished
+fn render_run(frame: &mut Frame, area: Rect, run: &mut TaskRun, focused: bool) {
+    match &run.summary {
+        Some(summary) => {
+            // reserve space below the output for the result of the task
+            let output_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(3)])
+                .split(area);
+            frame.render_widget(summary_banner(summary), output_chunks[1]);
+            render_output(
+                frame,
+                output_chunks[0],
+                &run.name,
+                &mut run.output,
+                None,
+                focused,
             );
+        }
+        None => render_output(
+            frame,
+            area,
+            &run.name,
+            &mut run.output,
+            run.step_progress.as_ref(),
+            focused,
+        ),
+    }
+}
 
-            // render the main section
-            frame.render_widget(list, chunks[1]);
-        }
-        app::CurrentScreen::Input => {
-            // create a list for the main section
-            let mut list_items = Vec::<ListItem>::new();
-            for entry in &app.items {
-                list_items.push(ListItem::new(Line::from(Span::styled(
-                    format!("{}", entry),
-                    Style::default().fg(Color::Yellow),
-                ))));
+/// Render one row per repository with its branch, distance to the upstream, changes and last
+/// commit
+fn render_dashboard(frame: &mut Frame, area: Rect, dashboard: &Dashboard, git_repo_dir: &str) {
+    let header = Row::new(vec![
+        "Repository",
+        "Branch",
+        "↑ahead ↓behind",
+        "Changed",
+        "Untracked",
+        "Last commit",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+
+    let rows: Vec<Row> = dashboard
+        .repos
+        .iter()
+        .enumerate()
+        .map(|(i, repo)| {
+            let style = if i == dashboard.selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            if let Some(error) = &repo.error {
+                return Row::new(vec![
+                    Cell::from(repo.name.clone()),
+                    Cell::from(error.clone()).style(Style::default().fg(Color::Red)),
+                ])
+                .style(style);
             }
+            let ahead_behind = match repo.ahead_behind {
+                Some((0, 0)) => "up to date".to_string(),
+                Some((ahead, behind)) => format!("↑{} ↓{}", ahead, behind),
+                None => "no upstream".to_string(),
+            };
+            let count = |count: usize| {
+                let color = if count > 0 {
+                    Color::Yellow
+                } else {
+                    Color::DarkGray
+                };
+                Cell::from(count.to_string()).style(Style::default().fg(color))
+            };
+            Row::new(vec![
+                Cell::from(repo.name.clone()),
+                Cell::from(
+                    repo.branch
+                        .clone()
+                        .unwrap_or_else(|| "(detached)".to_string()),
+                ),
+                Cell::from(ahead_behind),
+                count(repo.changed),
+                count(repo.untracked),
+                Cell::from(repo.last_commit.clone().unwrap_or_default()),
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let mut title = format!("Repositories in {}", git_repo_dir);
+    if dashboard.scanning {
+        title.push_str(" (refreshing...)");
+    } else if dashboard.repos.is_empty() {
+        title.push_str(": none found");
+    }
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(20),
+            Constraint::Percentage(15),
+            Constraint::Length(14),
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .title_bottom(
+                "↑/↓: select  r: refresh  p: review and push  c: clone from forge  \
+                 f/u/P: fetch/pull/push all  d: dirty  b: last results  Esc: back",
+            ),
+    );
+    let mut state = TableState::default().with_selected(Some(dashboard.selected));
+    frame.render_stateful_widget(table, area, &mut state);
+}
 
-            let list = List::new(list_items);
+/// Render the result of the bulk operation per repository. Repositories that are not processed yet
+/// are shown as pending.
+fn render_bulk(frame: &mut Frame, area: Rect, bulk: &BulkRun) {
+    let header = Row::new(vec!["Repository", "Result", "Reason"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let rows: Vec<Row> = bulk
+        .results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| {
+            let (label, color) = match result.outcome {
+                None => ("pending", Color::DarkGray),
+                Some(Outcome::Ok) => ("ok", Color::Green),
+                Some(Outcome::Failed) => ("failed", Color::Red),
+                Some(Outcome::Skipped) => ("skipped", Color::DarkGray),
+                Some(Outcome::Dirty) => ("dirty", Color::Yellow),
+            };
+            let style = if i == bulk.selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from(result.name.clone()),
+                Cell::from(label).style(Style::default().fg(color)),
+                Cell::from(result.reason.clone()),
+            ])
+            .style(style)
+        })
+        .collect();
 
-            // render the main section
-            frame.render_widget(list, chunks[1]);
+    let done = bulk
+        .results
+        .iter()
+        .filter(|result| result.outcome.is_some())
+        .count();
+    let title = if bulk.results.is_empty() {
+        format!("{}: no repositories found", bulk.op.title())
+    } else if bulk.is_running() {
+        format!(
+            "{}: {}/{} done...",
+            bulk.op.title(),
+            done,
+            bulk.results.len()
+        )
+    } else {
+        let mut title = format!(
+            "{}: {} ok, {} failed, {} skipped",
+            bulk.op.title(),
+            bulk.count(Outcome::Ok),
+            bulk.count(Outcome::Failed),
+            bulk.count(Outcome::Skipped)
+        );
+        if bulk.count(Outcome::Dirty) > 0 {
+            title.push_str(&format!(", {} dirty", bulk.count(Outcome::Dirty)));
+        }
+        title
+    };
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(25),
+            Constraint::Length(9),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .title_bottom("↑/↓: select  Esc: back to the dashboard"),
+    );
+    let mut state = TableState::default().with_selected(Some(bulk.selected));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+/// Render the repositories of the forge with a checkbox to select them for cloning. Repositories
+/// that are cloned already are marked and can not be selected.
+fn render_clone_list(frame: &mut Frame, area: Rect, clone_list: &CloneList, forge: &ForgeConfig) {
+    let items: Vec<ListItem> = clone_list
+        .repos
+        .iter()
+        .enumerate()
+        .map(|(i, repo)| {
+            let style = if i == clone_list.selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let checkbox = if repo.cloned {
+                Span::styled("[cloned] ", Style::default().fg(Color::Green))
+            } else if clone_list.marked[i] {
+                Span::raw("[x] ")
+            } else {
+                Span::raw("[ ] ")
+            };
+            ListItem::new(Line::from(vec![
+                checkbox,
+                Span::raw(repo.name.clone()),
+                Span::styled(
+                    format!("  {}", repo.description),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]))
+            .style(style)
+        })
+        .collect();
+
+    let source = forge.base_url().unwrap_or_default();
+    let title = if clone_list.loading {
+        format!("Repositories at {} (loading...)", source)
+    } else if !clone_list.skipped.is_empty() {
+        format!(
+            "Repositories at {}: {} selected, {} invalid skipped",
+            source,
+            clone_list.marked_repos().len(),
+            clone_list.skipped.len()
+        )
+    } else {
+        format!(
+            "Repositories at {}: {} selected",
+            source,
+            clone_list.marked_repos().len()
+        )
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title_bottom("space: select  a: all  Enter: clone  r: reload  Esc: back");
+
+    match &clone_list.error {
+        Some(error) => {
+            let error = Paragraph::new(format!("Failed to list the repositories: {}", error))
+                .style(Style::default().fg(Color::Red))
+                .wrap(Wrap { trim: false })
+                .block(block);
+            frame.render_widget(error, area);
+        }
+        None => {
+            // scroll the list so that the highlighted repository is visible
+            let mut state = ListState::default().with_selected(Some(clone_list.selected));
+            frame.render_stateful_widget(List::new(items).block(block), area, &mut state);
         }
-        _ => {}
     }
+}
 
-    let input_field: Line;
+/// Render the changed files with their stage state next to the diff of the highlighted file and
+/// the commit message editor below them
+fn render_review(frame: &mut Frame, area: Rect, review: &Review) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(8)])
+        .split(area);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+        .split(rows[0]);
+    let focused_border = |focus: ReviewFocus| {
+        if review.focus == focus {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        }
+    };
 
-    if app.activate_input_field {
-        if app.show_password_prompt {
-            let masked_input: String = "*".repeat(app.input_buffer.len());
-            input_field = Line::from(vec![
-                Span::styled("> ", Style::default().fg(Color::Green)),
-                Span::raw(masked_input),
-            ]);
+    let items: Vec<ListItem> = review
+        .files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let (marker, color) = match (file.staged, file.unstaged) {
+                (true, false) => ("[x] ", Color::Green),
+                (true, true) => ("[~] ", Color::Yellow),
+                _ => ("[ ] ", Color::Red),
+            };
+            let mut style = Style::default().fg(color);
+            if i == review.selected {
+                style = style.add_modifier(Modifier::BOLD | Modifier::REVERSED);
+            }
+            let untracked = if file.untracked { " (new)" } else { "" };
+            ListItem::new(format!("{}{}{}", marker, file.path, untracked)).style(style)
+        })
+        .collect();
+    let mut title = format!("Changes in {}", review.repo.display());
+    if review.is_busy() {
+        title.push_str(": reading...");
+    } else if review.files.is_empty() {
+        title.push_str(": nothing to commit");
+    }
+    let files = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(focused_border(ReviewFocus::Files))
+            .title(title)
+            .title_bottom("space: stage/unstage  r: refresh"),
+    );
+    let mut state = ListState::default().with_selected(Some(review.selected));
+    frame.render_stateful_widget(files, columns[0], &mut state);
+
+    let diff_lines: Vec<Line> = match &review.error {
+        Some(error) => vec![Line::styled(error.clone(), Style::default().fg(Color::Red))],
+        None => review
+            .diff
+            .iter()
+            .map(|line| {
+                let color = if line.starts_with("+++") || line.starts_with("---") {
+                    Color::White
+                } else if line.starts_with('+') {
+                    Color::Green
+                } else if line.starts_with('-') {
+                    Color::Red
+                } else if line.starts_with("@@") {
+                    Color::Cyan
+                } else {
+                    Color::Gray
+                };
+                Line::styled(line.clone(), Style::default().fg(color))
+            })
+            .collect(),
+    };
+    let diff = Paragraph::new(diff_lines)
+        .scroll((review.diff_scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Diff")
+                .title_bottom("PgUp/PgDn: scroll"),
+        );
+    frame.render_widget(diff, columns[1]);
+
+    // show the lines around the cursor, the cursor is drawn as block
+    let visible = rows[1].height.saturating_sub(2) as usize;
+    let first = (review.message.row + 1).saturating_sub(visible.max(1));
+    let message_lines: Vec<Line> = review
+        .message
+        .lines
+        .iter()
+        .enumerate()
+        .skip(first)
+        .map(|(row, line)| {
+            if review.focus != ReviewFocus::Message || row != review.message.row {
+                return Line::raw(line.clone());
+            }
+            let (before, rest) = line.split_at(
+                line.char_indices()
+                    .nth(review.message.col)
+                    .map_or(line.len(), |(i, _)| i),
+            );
+            let mut rest_chars = rest.chars();
+            let cursor = rest_chars.next().map_or(" ".to_string(), String::from);
+            Line::from(vec![
+                Span::raw(before.to_string()),
+                Span::styled(cursor, Style::default().add_modifier(Modifier::REVERSED)),
+                Span::raw(rest_chars.as_str().to_string()),
+            ])
+        })
+        .collect();
+    let message = Paragraph::new(message_lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(focused_border(ReviewFocus::Message))
+            .title("Commit message")
+            .title_bottom("Tab: switch files/message  Ctrl+P: commit and push  Esc: cancel"),
+    );
+    frame.render_widget(message, rows[1]);
+}
+
+/// Render the toast in the lower right corner of the area
+fn render_toast(frame: &mut Frame, area: Rect, toast: &Toast) {
+    let (title, color) = if toast.error {
+        ("Error", Color::Red)
+    } else {
+        ("Info", Color::Green)
+    };
+    let width = area.width.min(70);
+    // the text is wrapped, reserve enough lines for the borders and the wrapped lines
+    let inner_width = width.saturating_sub(2).max(1) as usize;
+    let lines: usize = toast
+        .message
+        .lines()
+        .map(|line| line.chars().count().div_ceil(inner_width).max(1))
+        .sum();
+    let height = (lines as u16 + 2).min(area.height);
+    let toast_area = Rect {
+        x: area.x + area.width - width,
+        y: area.y + area.height - height,
+        width,
+        height,
+    };
+
+    let paragraph = Paragraph::new(toast.message.as_str())
+        .wrap(Wrap { trim: false })
+        .style(Style::default().fg(color))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(Clear, toast_area);
+    frame.render_widget(paragraph, toast_area);
+}
+
+/// Render the parameters of a task. Every parameter takes one line with its value and a hint of
+/// the allowed values, validation errors are shown in the line below.
+fn render_form(frame: &mut Frame, area: Rect, form: &Form) {
+    let mut lines = Vec::new();
+    for (i, param) in form.task.params.iter().enumerate() {
+        let focused = i == form.focused;
+        let value = &form.values[i];
+        let shown_value = match &param.kind {
+            ParamKind::Password => "*".repeat(value.chars().count()),
+            ParamKind::Boolean => format!("[{}]", if value.as_str() == "true" { "x" } else { " " }),
+            ParamKind::Choice { .. } => format!("< {} >", value.as_str()),
+            _ => value.to_string(),
+        };
+        let hint = match &param.kind {
+            ParamKind::String | ParamKind::Password => String::new(),
+            ParamKind::Boolean => "space: toggle".to_string(),
+            ParamKind::Choice { choices } => format!("←/→: {}", choices.join(", ")),
+            ParamKind::Path { .. } => "path".to_string(),
+            ParamKind::Integer { min, max } => match (min, max) {
+                (Some(min), Some(max)) => format!("number {}..{}", min, max),
+                (Some(min), None) => format!("number >= {}", min),
+                (None, Some(max)) => format!("number <= {}", max),
+                (None, None) => "number".to_string(),
+            },
+        };
+        let label_style = if focused {
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD)
         } else {
-            input_field = Line::from(vec![
-                Span::styled("> ", Style::default().fg(Color::Green)),
-                Span::raw(&app.input_buffer),
-            ]);
+            Style::default()
+        };
+        let cursor =
+            if focused && !matches!(param.kind, ParamKind::Boolean | ParamKind::Choice { .. }) {
+                "_"
+            } else {
+                ""
+            };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}: ", param.label()), label_style),
+            Span::raw(format!("{}{}", shown_value, cursor)),
+            Span::styled(format!("  {}", hint), Style::default().fg(Color::DarkGray)),
+        ]));
+        if let Some(error) = &form.errors[i] {
+            lines.push(Line::styled(
+                format!("  {}", error),
+                Style::default().fg(Color::Red),
+            ));
         }
+    }
+
+    let form_widget = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{}: parameters", form.task.name))
+            .title_bottom("Tab/↑/↓: move  Enter: run  Esc: cancel"),
+    );
+    frame.render_widget(form_widget, area);
+}
+
+/// Render the part of the output that is scrolled to together with a scrollbar.
+/// The running step is shown as progress indicator in the title.
+fn render_output(
+    frame: &mut Frame,
+    area: Rect,
+    name: &str,
+    output: &mut OutputBuffer,
+    step_progress: Option<&(usize, usize, String)>,
+    focused: bool,
+) {
+    let title = if output.follow {
+        format!("Output of {} (following)", name)
+    } else {
+        format!("Output of {}", name)
+    };
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if focused {
+        block = block.border_style(Style::default().fg(Color::Yellow));
+    }
+    if let Some((index, total, name)) = step_progress {
+        block = block.title(
+            Line::styled(
+                format!("{}/{}: {}", index + 1, total, name),
+                Style::default().fg(Color::Cyan),
+            )
+            .right_aligned(),
+        );
+    }
+    let block = block.title_bottom(
+        "PgUp/PgDn/Home/End: scroll  Ctrl+F: follow  Ctrl+C: cancel  Ctrl+←/→: tabs  Ctrl+W: close  \
+         Tab: menu",
+    );
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    // the height is needed by the key handling to scroll by one page
+    output.viewport_height = inner.height as usize;
+    let top = output.top();
+
+    // create a list for the main section
+    let mut list_items = Vec::<ListItem>::new();
+    for (stream, entry) in output.lines.iter().skip(top).take(inner.height as usize) {
+        // stderr is shown in red to distinguish it from the regular output. Colors set
+        // by the command itself take precedence.
+        let color = match stream {
+            Stream::Stdout => Color::Yellow,
+            Stream::Stderr => Color::Red,
+            Stream::Info => Color::Cyan,
+        };
+        list_items.push(ListItem::new(
+            entry.clone().patch_style(Style::default().fg(color)),
+        ));
+    }
+    frame.render_widget(List::new(list_items), inner);
 
-        let input_line = Paragraph::new(input_field)
-            .block(Block::default().borders(Borders::ALL).title("Input"))
-            .style(Style::default().fg(Color::White));
-        frame.render_widget(&input_line, chunks[2]);
+    let mut scrollbar_state =
+        ScrollbarState::new(output.lines.len().saturating_sub(inner.height as usize))
+            .position(top)
+            .viewport_content_length(inner.height as usize);
+    frame.render_stateful_widget(
+        Scrollbar::new(ScrollbarOrientation::VerticalRight),
+        area,
+        &mut scrollbar_state,
+    );
+}
+
+/// Banner that shows if the task succeeded together with the number of successful steps,
+/// the reason of the failure and the duration
+fn summary_banner(summary: &TaskSummary) -> Paragraph<'static> {
+    let failed = summary.failed_step();
+    let (text, color) = match failed.and_then(|(_, step)| step.completion) {
+        _ if summary.cancelled => ("Cancelled", Color::Yellow),
+        None if summary.success() => ("Finished successfully", Color::Green),
+        Some(completion) if completion.timed_out => ("Timed out", Color::Red),
+        Some(completion) if completion.stopped_with.is_some() => ("Cancelled", Color::Yellow),
+        _ => ("Failed", Color::Red),
+    };
+    let mut spans = vec![
+        Span::styled(
+            text,
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(format!(
+            " ({}/{} steps succeeded) after {:.2}s",
+            summary.succeeded(),
+            summary.steps.len(),
+            summary.duration.as_secs_f64()
+        )),
+    ];
+    if let Some((index, step)) = failed {
+        if let Some(completion) = step.completion {
+            let attempt = if completion.attempt > 1 {
+                format!(" in attempt {}", completion.attempt)
+            } else {
+                String::new()
+            };
+            spans.push(Span::raw(format!(
+                ", step {} \"{}\": {}{}",
+                index + 1,
+                step.name,
+                completion.reason(),
+                attempt
+            )));
+        }
     }
+    Paragraph::new(Line::from(spans)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(color)),
+    )
 }
 
-/// helper function to create a centered rect using up certain percentage of the available rect `r`
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
-    // Cut the given rectangle into three vertical pieces
-    let popup_layout = Layout::default()
+/// Startup screen that lists the problems of the configuration
+pub fn config_errors(frame: &mut Frame, errors: &ConfigErrors) {
+    let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Percentage((100 - percent_y) / 2),
-            Constraint::Percentage(percent_y),
-            Constraint::Percentage((100 - percent_y) / 2),
-        ])
-        .split(r);
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(frame.area());
 
-    // Then cut the middle vertical piece into three width-wise pieces
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage((100 - percent_x) / 2),
-            Constraint::Percentage(percent_x),
-            Constraint::Percentage((100 - percent_x) / 2),
-        ])
-        .split(popup_layout[1])[1] // Return the middle chunk
+    let title = Paragraph::new(Text::styled("Linutil", Style::default().fg(Color::Green)))
+        .block(Block::default().borders(Borders::ALL));
+    frame.render_widget(title, chunks[0]);
+
+    let lines: Vec<Line> = errors
+        .0
+        .iter()
+        .map(|problem| Line::styled(problem.to_string(), Style::default().fg(Color::Red)))
+        .collect();
+    let problems = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                "Invalid configuration: {} problem(s)",
+                errors.0.len()
+            ))
+            .title_bottom("Fix the configuration and restart  q/Esc: quit"),
+    );
+    frame.render_widget(problems, chunks[1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ratatui::{buffer::Buffer, widgets::Widget};
+
+    use super::*;
+    use crate::functions::{Completion, StepResult};
+
+    fn completion(code: Option<i32>, signal: Option<i32>, stopped_with: Option<i32>) -> Completion {
+        Completion {
+            code,
+            signal,
+            stopped_with,
+            timed_out: false,
+            attempt: 1,
+            duration: Duration::from_millis(500),
+        }
+    }
+
+    /// Summary of a task with a successful first step and the given result of the second step
+    fn summary(second: Completion) -> TaskSummary {
+        let step = |name: &str, completion| StepResult {
+            name: name.to_string(),
+            continue_on_error: false,
+            completion,
+        };
+        TaskSummary {
+            steps: vec![
+                step("fetch", Some(completion(Some(0), None, None))),
+                step("build", Some(second)),
+                step("install", None),
+            ],
+            duration: Duration::from_millis(1500),
+            cancelled: false,
+        }
+    }
+
+    /// Text of the banner without its border
+    fn banner_text(summary: &TaskSummary) -> String {
+        let area = Rect::new(0, 0, 160, 3);
+        let mut buffer = Buffer::empty(area);
+        summary_banner(summary).render(area, &mut buffer);
+        (1..159)
+            .map(|x| buffer[(x, 1)].symbol())
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn banner_shows_the_failed_step_and_duration() {
+        let mut success = summary(completion(Some(0), None, None));
+        success.steps.pop();
+        assert_eq!(
+            banner_text(&success),
+            "Finished successfully (2/2 steps succeeded) after 1.50s"
+        );
+        assert_eq!(
+            banner_text(&summary(completion(None, Some(9), None))),
+            "Failed (1/3 steps succeeded) after 1.50s, step 2 \"build\": terminated by SIGKILL"
+        );
+        assert_eq!(
+            banner_text(&summary(completion(None, Some(2), Some(2)))),
+            "Cancelled (1/3 steps succeeded) after 1.50s, step 2 \"build\": stopped with SIGINT, \
+             terminated by SIGINT"
+        );
+        let timed_out = Completion {
+            timed_out: true,
+            attempt: 2,
+            ..completion(Some(124), None, Some(2))
+        };
+        assert_eq!(
+            banner_text(&summary(timed_out)),
+            "Timed out (1/3 steps succeeded) after 1.50s, step 2 \"build\": timed out, stopped \
+             with SIGINT, exit code 124 in attempt 2"
+        );
+    }
 }
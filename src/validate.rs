@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use nix::unistd::{access, AccessFlags};
+use serde::de::DeserializeOwned;
+use toml_edit::{ImDocument, TableLike};
+
+use crate::{app::Config, functions::expand_home};
+
+/// Where a config value comes from, so that the user can find the cause of a problem
+#[derive(Debug, Clone)]
+pub enum Location {
+    // line and column (both starting at 1) are unknown if the whole file is affected
+    File {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+    },
+    CommandLine(String), // the option that set the value
+}
+
+/// A single problem of the configuration
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(location: Option<Location>, message: impl Into<String>) -> Problem {
+        Problem {
+            location,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.location {
+            Some(Location::File {
+                path,
+                position: Some((line, column)),
+            }) => write!(f, "{}:{}:{}: ", path.display(), line, column)?,
+            Some(Location::File {
+                path,
+                position: None,
+            }) => write!(f, "{}: ", path.display())?,
+            Some(Location::CommandLine(option)) => write!(f, "command line ({}): ", option)?,
+            None => {}
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// All problems that were found while reading the configuration
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<Problem>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, problem) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+// problems of other files (e.g. the task catalog) are shown like the config problems
+impl From<Problem> for ConfigErrors {
+    fn from(problem: Problem) -> ConfigErrors {
+        ConfigErrors(vec![problem])
+    }
+}
+
+/// Locations of the config keys (dotted, e.g. `forge.url`). Like the values, a key of a later
+/// file or of the command line replaces the location of an earlier one.
+#[derive(Debug, Default)]
+pub struct KeyLocations(HashMap<String, Location>);
+
+impl KeyLocations {
+    /// Remember the position of every key of the file
+    pub fn add_file(&mut self, path: &Path, content: &str) {
+        if let Ok(document) = ImDocument::parse(content) {
+            self.add_table(path, content, document.as_table(), "");
+        }
+    }
+
+    fn add_table(&mut self, path: &Path, content: &str, table: &dyn TableLike, prefix: &str) {
+        for (key, item) in table.iter() {
+            let dotted_key = format!("{}{}", prefix, key);
+            let span = table.key(key).and_then(|key| key.span());
+            self.0.insert(
+                dotted_key.clone(),
+                Location::File {
+                    path: path.to_path_buf(),
+                    position: span.map(|span| position(content, span.start)),
+                },
+            );
+            if let Some(sub_table) = item.as_table_like() {
+                self.add_table(path, content, sub_table, &format!("{}.", dotted_key));
+            }
+        }
+    }
+
+    pub fn add_override(&mut self, key: &str) {
+        self.0.insert(
+            key.to_string(),
+            Location::CommandLine(format!("--{}", key.replace('_', "-"))),
+        );
+    }
+
+    /// Location of the key or, if it is not known, of the closest table that contains it
+    pub fn get(&self, key: &str) -> Option<Location> {
+        let mut key = key;
+        loop {
+            if let Some(location) = self.0.get(key) {
+                return Some(location.clone());
+            }
+            key = key.rsplit_once('.')?.0;
+        }
+    }
+}
+
+/// Line and column (both starting at 1) of a byte offset
+pub fn position(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Parse a config file or the task catalog. A syntax error or a value of the wrong type is
+/// reported at the position where it was found.
+pub fn parse_file<T: DeserializeOwned>(path: &Path, content: &str) -> Result<T, Problem> {
+    toml::from_str(content).map_err(|err: toml::de::Error| {
+        Problem::new(
+            Some(Location::File {
+                path: path.to_path_buf(),
+                position: err.span().map(|span| position(content, span.start)),
+            }),
+            err.message().trim_end(),
+        )
+    })
+}
+
+/// Deserialize the merged config and check its values. Keys that are not part of the config
+/// and values of the wrong type are reported as well.
+pub fn check_config(merged: toml::Table, locations: &KeyLocations) -> Result<Config, ConfigErrors> {
+    let mut problems = Vec::new();
+    let mut unknown_keys: Vec<String> = Vec::new();
+    let mut collect_unknown = |path: serde_ignored::Path| unknown_keys.push(path.to_string());
+    let deserializer =
+        serde_ignored::Deserializer::new(toml::Value::Table(merged), &mut collect_unknown);
+    let result: Result<Config, _> = serde_path_to_error::deserialize(deserializer);
+
+    for key in unknown_keys {
+        problems.push(Problem::new(
+            locations.get(&key),
+            format!("unknown key `{}`", key),
+        ));
+    }
+    let config = match result {
+        Ok(config) => Some(config),
+        Err(err) => {
+            let key = err.path().to_string();
+            problems.push(Problem::new(
+                locations.get(&key),
+                format!("`{}`: {}", key, err.inner().message().trim_end()),
+            ));
+            None
+        }
+    };
+    if let Some(config) = &config {
+        problems.extend(check_values(config, locations));
+    }
+
+    match config {
+        Some(mut config) if problems.is_empty() => {
+            config.warnings = check_warnings(&config, locations);
+            Ok(config)
+        }
+        _ => Err(ConfigErrors(problems)),
+    }
+}
+
+/// Problems of a valid config that only matter for some screens, the app still starts
+fn check_warnings(config: &Config, locations: &KeyLocations) -> Vec<Problem> {
+    let mut warnings = Vec::new();
+    let git_repo_dir = PathBuf::from(expand_home(&config.git_repo_dir));
+    if !git_repo_dir.exists() {
+        warnings.push(Problem::new(
+            locations.get("git_repo_dir"),
+            format!(
+                "`git_repo_dir`: {} does not exist, it is created by the first clone",
+                git_repo_dir.display()
+            ),
+        ));
+    }
+    warnings
+}
+
+/// Check the values of a config that could be deserialized
+fn check_values(config: &Config, locations: &KeyLocations) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut problem =
+        |key: &str, message: String| problems.push(Problem::new(locations.get(key), message));
+
+    if config.name.is_empty() {
+        problem(
+            "name",
+            "required key `name` is missing (and $USER is not set)".to_string(),
+        );
+    }
+    if config.scrollback == 0 {
+        problem("scrollback", "`scrollback` must be at least 1".to_string());
+    }
+
+    // a missing directory is only a warning, it is created by the first clone
+    let git_repo_dir = PathBuf::from(expand_home(&config.git_repo_dir));
+    if git_repo_dir.exists() && !git_repo_dir.is_dir() {
+        problem(
+            "git_repo_dir",
+            format!(
+                "`git_repo_dir`: {} is not a directory",
+                git_repo_dir.display()
+            ),
+        );
+    } else if git_repo_dir.exists() && access(&git_repo_dir, AccessFlags::W_OK).is_err() {
+        problem(
+            "git_repo_dir",
+            format!("`git_repo_dir`: {} is not writable", git_repo_dir.display()),
+        );
+    }
+
+    if config.forge.base_url().is_none() {
+        problem(
+            "forge",
+            format!(
+                "`forge.url` is required for the forge type {}",
+                config.forge.kind.name()
+            ),
+        );
+    }
+
+    if let Some(task_file) = &config.task_file {
+        let task_file = PathBuf::from(expand_home(task_file));
+        if !task_file.is_file() {
+            problem(
+                "task_file",
+                format!("`task_file`: {} does not exist", task_file.display()),
+            );
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(content: &str) -> Result<Config, ConfigErrors> {
+        check_config(toml::from_str(content).unwrap(), &KeyLocations::default())
+    }
+
+    #[test]
+    fn missing_git_repo_dir_is_a_warning() {
+        let config = check("name = \"t\"\ngit_repo_dir = \"/nonexistent/git_repos\"").unwrap();
+        assert_eq!(config.warnings.len(), 1);
+        assert!(config.warnings[0].message.contains("does not exist"));
+    }
+
+    #[test]
+    fn git_repo_dir_must_be_a_directory() {
+        let file =
+            std::env::temp_dir().join(format!("linutil_rs-not-a-dir-{}", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let result = check(&format!("name = \"t\"\ngit_repo_dir = {:?}", file));
+        std::fs::remove_file(&file).unwrap();
+        let errors = result.unwrap_err();
+        assert!(errors.to_string().contains("is not a directory"));
+    }
+
+    #[test]
+    fn forge_without_default_url_is_named() {
+        let errors = check("name = \"t\"\n[forge]\ntype = \"forgejo\"").unwrap_err();
+        assert!(errors
+            .to_string()
+            .contains("`forge.url` is required for the forge type gitea"));
+    }
+}
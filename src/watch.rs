@@ -0,0 +1,99 @@
+use std::{
+    fs::metadata,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+// how often the watched files are checked for changes
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Detects changes of files by comparing their modification time and size. Files that do not
+/// exist yet are watched as well, so that creating them counts as a change.
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<(SystemTime, u64)>)>,
+    last_check: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(files: Vec<PathBuf>) -> FileWatcher {
+        FileWatcher {
+            files: files
+                .into_iter()
+                .map(|file| {
+                    let state = file_state(&file);
+                    (file, state)
+                })
+                .collect(),
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Returns true if one of the files was changed, created or deleted since the last check.
+    /// The files are only checked once per WATCH_INTERVAL.
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let mut changed = false;
+        for (file, state) in &mut self.files {
+            let new_state = file_state(file);
+            if new_state != *state {
+                *state = new_state;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn file_state(file: &PathBuf) -> Option<(SystemTime, u64)> {
+    let metadata = metadata(file).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Check the files as if the interval had passed
+    fn check(watcher: &mut FileWatcher) -> bool {
+        watcher.last_check -= WATCH_INTERVAL;
+        watcher.changed()
+    }
+
+    #[test]
+    fn created_changed_and_deleted_files_are_detected() {
+        let file =
+            std::env::temp_dir().join(format!("linutil_rs-watch-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&file);
+        let mut watcher = FileWatcher::new(vec![file.clone()]);
+        assert!(!check(&mut watcher));
+
+        fs::write(&file, "name = \"a\"").unwrap();
+        assert!(check(&mut watcher));
+        assert!(!check(&mut watcher));
+
+        fs::write(&file, "name = \"ab\"").unwrap();
+        assert!(check(&mut watcher));
+
+        fs::remove_file(&file).unwrap();
+        assert!(check(&mut watcher));
+        assert!(!check(&mut watcher));
+    }
+
+    #[test]
+    fn files_are_checked_once_per_interval() {
+        let file =
+            std::env::temp_dir().join(format!("linutil_rs-watch-interval-{}", std::process::id()));
+        let _ = fs::remove_file(&file);
+        let mut watcher = FileWatcher::new(vec![file.clone()]);
+        fs::write(&file, "").unwrap();
+        // the change is only reported after the interval
+        assert!(!watcher.changed());
+        assert!(check(&mut watcher));
+        fs::remove_file(&file).unwrap();
+    }
+}